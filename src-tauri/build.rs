@@ -23,8 +23,12 @@ use kolekk_types::{
         },
     },
     objects::{
-        Bookmark, BookmarkSource, Content, Fields, Group, Image, Indexed, Meta, Notes,
-        SearchableEntry, Tag, Taggable, Tagged, TypeFacet, WithContext,
+        ArchiveConfig, ArchiveKind, BackupInfo, BatchProgress, BatchResult, BodySnippet, Bookmark,
+        BookmarkArchive, BookmarkExportFormat, BookmarkSource, BookmarkSourceFormat, Content,
+        Fields, Group, Image, ImportReport, Indexed, LibraryStats, LinkProblem, LinkStatus, Meta,
+        Notes, ObjectDraft, RangeFilter, SavedSearch, SearchFilters, SearchSort, SearchableEntry,
+        SourceRefreshReport, StorageStats, SyncReport, Tag, Taggable, Tagged, TypeFacet,
+        WithContext,
     },
     utility::{
         BasePath, ByteArrayFile, DdpInfo, DirFiles, DragDropData, DragDropPaste, Path, Source,
@@ -70,10 +74,31 @@ fn main() {
         Image,
         Bookmark,
         BookmarkSource,
+        BookmarkSourceFormat,
         Content,
         Notes,
         Tag,
         Group,
+        SavedSearch,
+        SearchFilters,
+        RangeFilter,
+        SearchSort,
+        BatchResult,
+        BatchProgress,
+        LibraryStats,
+        StorageStats,
+        BackupInfo,
+        SyncReport,
+        ObjectDraft,
+        ImportReport,
+        BookmarkExportFormat,
+        LinkStatus,
+        LinkProblem,
+        BookmarkArchive,
+        ArchiveKind,
+        ArchiveConfig,
+        BodySnippet,
+        SourceRefreshReport,
         Meta<(), ()>,
         Taggable<()>,
         Tagged<()>,
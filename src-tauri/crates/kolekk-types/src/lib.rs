@@ -884,6 +884,85 @@ pub mod objects {
         pub url: String,
         pub description: Option<String>,
         pub source: Option<Id>,
+        // result of the last link check
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub link: Option<LinkStatus>,
+        // offline copy of the page
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub archive: Option<BookmarkArchive>,
+        // when the text of the page was last indexed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub crawled: Option<u64>,
+        // og:image or twitter:image
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub image: Option<String>,
+        // favicon url
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub icon: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub site_name: Option<String>,
+        // rel=canonical or og:url of the page
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub canonical: Option<String>,
+        // the tags that came from the file of the source. the other ones were added by hand
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        pub source_tags: Option<Vec<Id>>,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct LinkStatus {
+        // None if the request failed
+        pub status: Option<u16>,
+        pub error: Option<String>,
+        // the url after following redirects, if it is different
+        pub redirect: Option<String>,
+        pub checked: u64,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct BookmarkArchive {
+        pub path: Path,
+        pub kind: ArchiveKind,
+        pub time: u64,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum ArchiveKind {
+        // the whole page with images and stylesheets inlined
+        #[default]
+        Page,
+        // only the main text of the page
+        Article,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Default)]
+    pub struct ArchiveConfig {
+        // archive bookmarks as soon as they are added
+        pub archive_on_add: bool,
+        pub kind: ArchiveKind,
+    }
+
+    // part of Fields::Body around the words that matched a search
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct BodySnippet {
+        pub fragment: String,
+        // byte ranges of fragment to highlight
+        pub highlighted: Vec<(usize, usize)>,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy)]
+    pub enum LinkProblem {
+        // failed requests and 4xx/5xx responses
+        Broken,
+        Redirected,
     }
 
     #[derive(Serialize, Deserialize, TS, Debug, Clone)]
@@ -893,6 +972,25 @@ pub mod objects {
         pub path: Path,
         pub last_checked: u64,
         pub mtime: i64,
+        #[serde(default)]
+        pub format: BookmarkSourceFormat,
+    }
+
+    // how the file of a BookmarkSource is parsed. detected from the file name when the source
+    // is added
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum BookmarkSourceFormat {
+        // the tagged markdown format
+        #[default]
+        Markdown,
+        // the 'Bookmarks' json file in chromium profiles
+        Chromium,
+        // org-mode file. headings are tags
+        Org,
+        // a note in an obsidian vault. its tags and wiki-links are tags of all its links
+        Obsidian,
+        // one url per line
+        UrlList,
     }
 
     #[derive(Serialize, Deserialize, TS, Debug, Clone)]
@@ -915,11 +1013,163 @@ pub mod objects {
     #[serde(tag = "object_type")]
     pub enum Tag {
         #[serde(rename = "main_tag")]
-        Main { name: String },
+        Main {
+            name: String,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            #[ts(optional)]
+            description: Option<String>,
+            // hex color. eg: "#ff8800"
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            #[ts(optional)]
+            color: Option<String>,
+            // an emoji or an icon name
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            #[ts(optional)]
+            icon: Option<String>,
+            // id of an object that represents this tag
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            #[ts(optional)]
+            cover: Option<Id>,
+        },
         #[serde(rename = "alias_tag")]
         Alias { name: String, alias_to: Id },
     }
 
+    // a query that is evaluated live every time it is opened. shows up as a collection of objects
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    #[serde(tag = "object_type")]
+    pub struct SavedSearch {
+        pub title: String,
+        pub query: String,
+        pub facet: TypeFacet,
+        pub filters: SearchFilters,
+        pub sort: SearchSort,
+        // number of results the last time this search was opened
+        pub cached_count: Option<u64>,
+        pub last_opened: u64,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Default)]
+    #[serde(default)]
+    pub struct SearchFilters {
+        // objects must have all of these tags
+        pub tags: Vec<Id>,
+        pub created: RangeFilter,
+        pub modified: RangeFilter,
+        pub last_interaction: RangeFilter,
+        // size of images in bytes
+        pub size: RangeFilter,
+    }
+
+    // min is inclusive, max is exclusive. None means unbounded
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy, Default)]
+    pub struct RangeFilter {
+        pub min: Option<u64>,
+        pub max: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy, Default)]
+    pub enum SearchSort {
+        #[default]
+        Relevance,
+        Newest,
+        Oldest,
+        RecentlyViewed,
+    }
+
+    // outcome of a batch operation for a single object
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct BatchResult {
+        pub id: Id,
+        pub error: Option<String>,
+    }
+
+    // sent as an event while large batch operations are running
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct BatchProgress {
+        pub operation: String,
+        pub done: usize,
+        pub total: usize,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy)]
+    pub enum BookmarkExportFormat {
+        // netscape bookmark html that browsers can import
+        Html,
+        Json,
+        Csv,
+    }
+
+    // outcome of importing bookmarks from a browser
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Default)]
+    pub struct ImportReport {
+        pub added: usize,
+        // already existed. their tags were merged
+        pub updated: usize,
+        // url with the reason it could not be imported
+        pub failed: Vec<WithContext<String, String>>,
+    }
+
+    // sent as "bookmark-source-refreshed" when a watched source was imported again
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Default)]
+    pub struct SourceRefreshReport {
+        pub source: Id,
+        pub title: String,
+        pub added: usize,
+        pub removed: usize,
+        // same url, but a different title or tags
+        pub changed: usize,
+        // url with the reason it could not be imported
+        pub failed: Vec<WithContext<String, String>>,
+    }
+
+    // an object that is not saved yet. used to suggest tags while adding it
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Default)]
+    #[serde(default)]
+    pub struct ObjectDraft {
+        pub title: Option<String>,
+        pub url: Option<String>,
+        // file name or path
+        pub path: Option<String>,
+        // tags already applied to it
+        pub tags: Vec<Id>,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct LibraryStats {
+        pub objects: Vec<WithContext<TypeFacet, u64>>,
+        pub tags: u64,
+        // most used tags with the number of objects tagged with them
+        pub top_tags: Vec<WithContext<Meta<Tag, TypeFacet>, u64>>,
+        // start of the day/month (unix secs, utc) with the number of objects added in it
+        pub added_per_day: Vec<WithContext<u64, u64>>,
+        pub added_per_month: Vec<WithContext<u64, u64>>,
+        pub storage: StorageStats,
+    }
+
+    // in bytes
+    #[derive(Serialize, Deserialize, TS, Debug, Clone, Copy)]
+    pub struct StorageStats {
+        pub images: u64,
+        pub thumbnails: u64,
+        pub index: u64,
+    }
+
+    // number of changes written to and applied from the sync dir
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct SyncReport {
+        pub sent: usize,
+        pub received: usize,
+    }
+
+    #[derive(Serialize, Deserialize, TS, Debug, Clone)]
+    pub struct BackupInfo {
+        // unix secs. also the name of the backup dir
+        pub timestamp: u64,
+        // in bytes
+        pub size: u64,
+    }
+
     // add a notes object and link it to other objects to give some more context to them
     // the linking can be done using the Group ojject
     // like: this show was recommended by this person
@@ -997,6 +1247,10 @@ pub mod objects {
         Chksum, // to check if file or some data is already in db or no
         Tag,
         Json,
+        Size, // size of the file in bytes
+        Prefix, // edge n-grams of Text for prefix matching
+        Body,   // long text like the content of a web page. weighs less than Text
+        Key,    // untokenized. objects with the same key are duplicates (canonical url of bookmarks)
     }
     impl Deref for Fields {
         type Target = str;
@@ -1013,6 +1267,10 @@ pub mod objects {
                 Self::Chksum => "chksum",
                 Self::Tag => "tag",
                 Self::Json => "json",
+                Self::Size => "size",
+                Self::Prefix => "prefix",
+                Self::Body => "body",
+                Self::Key => "key",
             }
         }
     }
@@ -1032,7 +1290,11 @@ pub mod objects {
         Group,
         Content,
         Notes,
+        SavedSearch,
+        // user temp data. "/temp/..."
         Temp(#[ts(type = "string")] Cow<'static, str>),
+        // app state that is never shown to the user. "/app_data/..." and "/cache/..."
+        Internal(#[ts(type = "string")] Cow<'static, str>),
     }
 
     impl AsRef<str> for TypeFacet {
@@ -1045,7 +1307,9 @@ pub mod objects {
                 Self::Group => "/group",
                 Self::Content => "/content",
                 Self::Notes => "/notes",
+                Self::SavedSearch => "/saved_search",
                 Self::Temp(s) => s,
+                Self::Internal(s) => s,
             }
         }
     }
@@ -1061,6 +1325,10 @@ pub mod objects {
                 "/group" => Self::Group,
                 "/content" => Self::Content,
                 "/notes" => Self::Notes,
+                "/saved_search" => Self::SavedSearch,
+                s if s.starts_with("/app_data/") || s.starts_with("/cache/") => {
+                    Self::Internal(s.to_string().into())
+                }
                 s => Self::Temp(s.to_string().into()),
             };
             Ok(t)
@@ -1077,6 +1345,10 @@ pub mod objects {
                 "/group" => Self::Group,
                 "/content" => Self::Content,
                 "/notes" => Self::Notes,
+                "/saved_search" => Self::SavedSearch,
+                s if s.starts_with("/app_data/") || s.starts_with("/cache/") => {
+                    Self::Internal(value.into())
+                }
                 _ => Self::Temp(value.into()),
             };
             Ok(t)
@@ -0,0 +1,450 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - archives are single html files in app_data_dir/archives, named after the bookmark id.
+// - images and stylesheets are inlined as data urls and scripts are removed, so the copy
+//   opens without network access. urls inside the stylesheets (fonts, backgrounds) are not
+//   followed.
+// - article archives keep only the main content of the page (see crawl::main_content).
+
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+use base64::Engine;
+use futures::StreamExt;
+use kolekk_types::{
+    objects::{
+        ArchiveConfig, ArchiveKind, BatchProgress, BatchResult, Bookmark, BookmarkArchive, Fields,
+        Id, TypeFacet,
+    },
+    utility::{BasePath, Path},
+};
+use reqwest::{Client, Url};
+use scraper::Html;
+use tantivy::{collector::TopDocs, query::TermQuery, schema::IndexRecordOption, Document, Term};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    bad_error::{BadError, Error, InferBadError},
+    batch::update_object,
+    config::AppConfig,
+    crawl::{main_content, page_title, selector},
+    database::{AppDatabase, AutoDbAble, DbAble, FacetFrom, RObject},
+    export::escape_html,
+    filesystem::get_path,
+};
+
+const ARCHIVE_DIR: &str = "archives";
+const TIMEOUT: Duration = Duration::from_secs(30);
+const RESOURCES_IN_PARALLEL: usize = 8;
+// larger images/stylesheets are left as links
+const MAX_RESOURCE_SIZE: usize = 5 * 1024 * 1024;
+
+impl AutoDbAble for ArchiveConfig {}
+
+pub struct ArchiveState {
+    config: Mutex<ArchiveConfig>,
+}
+
+pub fn init_archive(app_handle: &AppHandle, db: &AppDatabase) -> Result<(), Error> {
+    app_handle.manage(ArchiveState {
+        config: Mutex::new(load_config(db)?),
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_archive_config(state: State<'_, ArchiveState>) -> Result<ArchiveConfig, Error> {
+    Ok(state.config.lock().infer_err()?.clone())
+}
+
+#[tauri::command]
+pub async fn set_archive_config(
+    db: State<'_, AppDatabase>,
+    state: State<'_, ArchiveState>,
+    config: ArchiveConfig,
+) -> Result<(), Error> {
+    save_config(db.inner(), &config)?;
+    *state.config.lock().infer_err()? = config;
+    Ok(())
+}
+
+// kind defaults to the one in ArchiveConfig. existing archives are replaced
+#[tauri::command]
+pub async fn archive_bookmarks(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    client: State<'_, Client>,
+    app: State<'_, AppHandle>,
+    state: State<'_, ArchiveState>,
+    ids: Vec<Id>,
+    kind: Option<ArchiveKind>,
+) -> Result<Vec<BatchResult>, Error> {
+    let kind = match kind {
+        Some(k) => k,
+        None => state.config.lock().infer_err()?.kind,
+    };
+    archive_all(
+        db.inner(),
+        config.inner(),
+        client.inner(),
+        app.inner(),
+        ids,
+        kind,
+    )
+    .await
+}
+
+// opens the archived copy with the default browser
+#[tauri::command]
+pub async fn open_bookmark_archive(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+    id: Id,
+) -> Result<(), Error> {
+    let db = db.inner();
+    let mut doc = db.get_doc(id)?;
+    let bk: RObject<Bookmark> = DbAble::take(db, &mut doc)?;
+    let archive = bk
+        .data
+        .data
+        .data
+        .archive
+        .bad_err("bookmark is not archived")?;
+    let path = get_path(&archive.path, config.inner());
+    if !path.exists() {
+        return None.bad_err("archived file is missing");
+    }
+    let url = Url::from_file_path(&path)
+        .ok()
+        .bad_err("bad archive path")?;
+    tauri::api::shell::open(&app.shell_scope(), url.as_str(), None).infer_err()?;
+    Ok(())
+}
+
+// called with the ids of newly added bookmarks. archives them in the background if
+// archive_on_add is set
+pub fn archive_on_add(app: &AppHandle, ids: Vec<Id>) -> Result<(), Error> {
+    let config = app
+        .state::<ArchiveState>()
+        .config
+        .lock()
+        .infer_err()?
+        .clone();
+    if !config.archive_on_add || ids.is_empty() {
+        return Ok(());
+    }
+    let handle = app.app_handle();
+    let _ = tokio::task::spawn(async move {
+        let _ = archive_all(
+            handle.state::<AppDatabase>().inner(),
+            handle.state::<AppConfig>().inner(),
+            handle.state::<Client>().inner(),
+            &handle,
+            ids,
+            config.kind,
+        )
+        .await
+        .dbg();
+    });
+    Ok(())
+}
+
+async fn archive_all(
+    db: &AppDatabase,
+    config: &AppConfig,
+    client: &Client,
+    app: &AppHandle,
+    ids: Vec<Id>,
+    kind: ArchiveKind,
+) -> Result<Vec<BatchResult>, Error> {
+    let total = ids.len();
+    let mut res = vec![];
+    for (i, id) in ids.into_iter().enumerate() {
+        let r = archive_bookmark(db, config, client, id, kind).await;
+        res.push(BatchResult {
+            id,
+            error: r.err().map(|e| e.0.into_owned()),
+        });
+        let _ = app
+            .emit_all(
+                "batch-progress",
+                BatchProgress {
+                    operation: "archive_bookmarks".into(),
+                    done: i + 1,
+                    total,
+                },
+            )
+            .infer_err()
+            .dbg();
+    }
+    Ok(res)
+}
+
+pub fn archive_path(id: Id) -> Path {
+    Path {
+        base: BasePath::AppDataDir,
+        path: PathBuf::from(ARCHIVE_DIR).join(format!("{}.html", id)),
+    }
+}
+
+// called when a bookmark is deleted. bookmarks without an archive have no file
+pub fn delete_archive(config: &AppConfig, id: Id) -> Result<(), Error> {
+    match std::fs::remove_file(get_path(&archive_path(id), config)) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        r => r.infer_err(),
+    }
+}
+
+async fn archive_bookmark(
+    db: &AppDatabase,
+    config: &AppConfig,
+    client: &Client,
+    id: Id,
+    kind: ArchiveKind,
+) -> Result<(), Error> {
+    let mut doc = db.get_doc(id)?;
+    let bk: RObject<Bookmark> = DbAble::take(db, &mut doc)?;
+    let url = Url::parse(&bk.data.data.data.url).infer_err()?;
+
+    let html = fetch_page(client, &url).await?;
+    let html = match kind {
+        ArchiveKind::Page => html,
+        ArchiveKind::Article => article(&html, &url)?,
+    };
+    let html = inline_resources(client, &html, &url).await?;
+
+    let path = archive_path(id);
+    let file = get_path(&path, config);
+    std::fs::create_dir_all(file.parent().bad_err("bad archive path")?).infer_err()?;
+    tokio::fs::write(&file, html).await.infer_err()?;
+
+    // the bookmark might have been edited while the page was downloading
+    let time = db.now_time()?;
+    update_object(db, id, |bk: &mut RObject<Bookmark>| {
+        bk.data.data.data.archive = Some(BookmarkArchive { path, kind, time });
+        Ok(())
+    })
+    .map_err(|e| {
+        // or deleted
+        if db.get_doc(id).is_err() {
+            let _ = delete_archive(config, id).dbg();
+        }
+        e
+    })
+}
+
+async fn fetch_page(client: &Client, url: &Url) -> Result<String, Error> {
+    let page = client
+        .get(url.clone())
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .infer_err()?
+        .error_for_status()
+        .infer_err()?;
+    let is_html = page
+        .headers()
+        .get("content-type")
+        .and_then(|c| c.to_str().ok())
+        .map(|c| c.contains("text/html"))
+        .unwrap_or(false);
+    if !is_html {
+        return None.bad_err("not a html page");
+    }
+    page.text().await.infer_err()
+}
+
+struct Resource {
+    // the attribute value as it appears in the serialized html
+    attr: String,
+    url: Url,
+}
+
+// Html is not Send, so the page is parsed and serialized before anything is downloaded
+fn prepare_page(html: &str, base: &Url) -> Result<(String, Vec<Resource>), Error> {
+    let mut doc = Html::parse_document(html);
+
+    // scripts would try to reach the network. picture sources and srcset would be preferred
+    // over the inlined src
+    let remove = selector("script, noscript, picture > source, iframe")?;
+    let ids = doc.select(&remove).map(|e| e.id()).collect::<Vec<_>>();
+    for id in ids {
+        if let Some(mut node) = doc.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    let mut dropped = vec![];
+    let mut resources = vec![];
+    for e in doc.select(&selector("img, link[rel~=stylesheet], link[rel~=icon]")?) {
+        let attr = if e.value().name() == "img" {
+            "src"
+        } else {
+            "href"
+        };
+        // integrity checks would fail on the inlined copies
+        for a in ["srcset", "integrity", "crossorigin"] {
+            if let Some(v) = e.value().attr(a) {
+                dropped.push(format!(" {}=\"{}\"", a, escape_attr(v)));
+            }
+        }
+        let Some(v) = e.value().attr(attr) else {
+            continue;
+        };
+        if v.starts_with("data:") {
+            continue;
+        }
+        if let Ok(url) = base.join(v) {
+            resources.push(Resource {
+                attr: format!("{}=\"{}\"", attr, escape_attr(v)),
+                url,
+            });
+        }
+    }
+
+    let mut html = doc.html();
+    for d in dropped {
+        html = html.replace(&d, "");
+    }
+    Ok((html, resources))
+}
+
+// the whole page if kind is Page, else the output of article()
+async fn inline_resources(client: &Client, html: &str, base: &Url) -> Result<String, Error> {
+    let (mut html, resources) = prepare_page(html, base)?;
+
+    let inlined = futures::stream::iter(resources)
+        .map(|r| async move {
+            let data = fetch_data_url(client, &r.url).await.dbg().ok()?;
+            Some((r.attr, data))
+        })
+        .buffer_unordered(RESOURCES_IN_PARALLEL)
+        .collect::<Vec<_>>()
+        .await;
+    for (attr, data) in inlined.into_iter().flatten() {
+        let name = attr.split('=').next().unwrap_or_default();
+        html = html.replace(&attr, &format!("{}=\"{}\"", name, data));
+    }
+
+    // links and whatever could not be inlined still point to the original site
+    let base_tag = format!("<base href=\"{}\">", escape_attr(base.as_str()));
+    if let Some(i) = html
+        .find("<head")
+        .and_then(|i| Some(i + html[i..].find('>')? + 1))
+    {
+        html.insert_str(i, &base_tag);
+    }
+    Ok(html)
+}
+
+async fn fetch_data_url(client: &Client, url: &Url) -> Result<String, Error> {
+    let res = client
+        .get(url.clone())
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .infer_err()?
+        .error_for_status()
+        .infer_err()?;
+    let mime = res
+        .headers()
+        .get("content-type")
+        .and_then(|c| c.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| "application/octet-stream".into());
+    if res
+        .content_length()
+        .map(|l| l as usize > MAX_RESOURCE_SIZE)
+        .unwrap_or(false)
+    {
+        return None.bad_err("resource too large");
+    }
+    let bytes = res.bytes().await.infer_err()?;
+    if bytes.len() > MAX_RESOURCE_SIZE {
+        return None.bad_err("resource too large");
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    Ok(format!("data:{};base64,{}", mime.replace('"', ""), data))
+}
+
+// a plain page with the readable parts of the main element
+fn article(html: &str, url: &Url) -> Result<String, Error> {
+    let doc = Html::parse_document(html);
+    let title = page_title(&doc).unwrap_or_else(|| url.to_string());
+    let mut body = String::new();
+    for e in main_content(&doc)? {
+        body.push_str(&e.html());
+        body.push('\n');
+    }
+
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\nbody {{ max-width: 45em; margin: 2em auto; padding: 0 1em; font-family: serif; line-height: 1.6; }}\nimg {{ max-width: 100%; height: auto; }}\npre {{ overflow-x: auto; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p><a href=\"{url}\">{url}</a></p>\n{body}</body>\n</html>\n",
+        title = escape_html(&title),
+        url = escape_html(url.as_str()),
+        body = body,
+    ))
+}
+
+// how html5ever serializes attribute values
+fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('\u{a0}', "&nbsp;")
+        .replace('"', "&quot;")
+}
+
+fn config_facet() -> tantivy::schema::Facet {
+    TypeFacet::Internal("/app_data/archive".into()).facet()
+}
+
+fn load_config(db: &AppDatabase) -> Result<ArchiveConfig, Error> {
+    let searcher = db.get_searcher();
+    let config = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &config_facet()),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(1),
+        )
+        .infer_err()?
+        .first()
+        .and_then(|&(_, add)| searcher.doc(add).ok())
+        .and_then(|mut doc| DbAble::take(db, &mut doc).ok())
+        .unwrap_or_default();
+    Ok(config)
+}
+
+fn save_config(db: &AppDatabase, config: &ArchiveConfig) -> Result<(), Error> {
+    let mut doc = Document::new();
+    doc.add_facet(db.get_field(Fields::Type), config_facet());
+    config.clone().add(db, &mut doc)?;
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_facet(
+        db.get_field(Fields::Type),
+        &config_facet(),
+    ));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::test_config;
+
+    #[test]
+    fn archives_of_deleted_bookmarks_are_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let file = get_path(&archive_path(7), &config);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, "<html></html>").unwrap();
+
+        delete_archive(&config, 7).unwrap();
+        assert!(!file.exists());
+        // bookmarks that were never archived
+        delete_archive(&config, 7).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use kolekk_types::objects::BackupInfo;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    bad_error::{BadError, Error, InferBadError},
+    config::AppConfig,
+    database::AppDatabase,
+    stats::dir_size,
+};
+
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60 * 6);
+const KEEP_DAILY: usize = 7;
+const KEEP_WEEKLY: usize = 4;
+const DAY: u64 = 60 * 60 * 24;
+
+#[derive(Clone)]
+struct BackupDirs {
+    backups: PathBuf,
+    index: PathBuf,
+    images: PathBuf,
+}
+
+impl BackupDirs {
+    fn new(conf: &AppConfig) -> Self {
+        Self {
+            backups: conf.app_data_dir.join("backups"),
+            index: conf.app_data_dir.join("database").join("tantivy"),
+            images: conf.app_data_dir.join("images"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    // relative to the images dir
+    path: PathBuf,
+    size: u64,
+    mtime: u64,
+}
+
+pub fn init_backups(app_handle: &AppHandle, conf: &AppConfig) {
+    let handle = app_handle.app_handle();
+    let dirs = BackupDirs::new(conf);
+    let _ = tokio::task::spawn(async move {
+        loop {
+            let since_last = list_backups_in(&dirs)
+                .ok()
+                .and_then(|b| b.into_iter().max())
+                .and_then(|t| {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .ok()?;
+                    Some(now.saturating_sub(Duration::from_secs(t)))
+                });
+            let wait = since_last
+                .map(|d| BACKUP_INTERVAL.saturating_sub(d))
+                .unwrap_or(Duration::ZERO);
+            tokio::time::sleep(wait).await;
+
+            let (handle, dirs) = (handle.clone(), dirs.clone());
+            let res = tokio::task::spawn_blocking(move || {
+                let db = handle.state::<AppDatabase>();
+                create_snapshot(db.inner(), &dirs).and_then(|_| apply_retention(&dirs))
+            })
+            .await
+            .infer_err()
+            .and_then(|r| r)
+            .dbg();
+            if res.is_err() {
+                // don't retry right away
+                tokio::time::sleep(BACKUP_INTERVAL).await;
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn list_backups(config: State<'_, AppConfig>) -> Result<Vec<BackupInfo>, Error> {
+    let dirs = BackupDirs::new(config.inner());
+    let mut backups = list_backups_in(&dirs)?;
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    let backups = backups
+        .into_iter()
+        .map(|t| BackupInfo {
+            timestamp: t,
+            size: dir_size(&dirs.backups.join(t.to_string())),
+        })
+        .collect();
+    Ok(backups)
+}
+
+#[tauri::command]
+pub async fn create_backup(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+) -> Result<BackupInfo, Error> {
+    let dirs = BackupDirs::new(config.inner());
+    let t = create_snapshot(db.inner(), &dirs)?;
+    apply_retention(&dirs)?;
+    Ok(BackupInfo {
+        timestamp: t,
+        size: dir_size(&dirs.backups.join(t.to_string())),
+    })
+}
+
+// the index can't be swapped while it is open, so the snapshot is staged
+// and the app restarts to pick it up
+#[tauri::command]
+pub async fn restore_backup(
+    app: State<'_, AppHandle>,
+    config: State<'_, AppConfig>,
+    timestamp: u64,
+) -> Result<(), Error> {
+    let dirs = BackupDirs::new(config.inner());
+    let snapshot = dirs.backups.join(timestamp.to_string()).join("tantivy");
+    if !snapshot.exists() {
+        return None.bad_err("backup does not exist");
+    }
+
+    let restore = staged_restore_dir(&dirs.index);
+    let partial = restore.with_file_name("tantivy_restore.partial");
+    for dir in [&restore, &partial] {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir).infer_err()?;
+        }
+    }
+    std::fs::create_dir_all(&partial).infer_err()?;
+    for entry in std::fs::read_dir(&snapshot).infer_err()? {
+        let entry = entry.infer_err()?;
+        let _ = std::fs::copy(entry.path(), partial.join(entry.file_name())).infer_err()?;
+    }
+    std::fs::rename(&partial, &restore).infer_err()?;
+
+    app.restart();
+    Ok(())
+}
+
+// called before the index is opened
+pub fn apply_staged_restore(index_dir: &Path) -> Result<(), Error> {
+    let restore = staged_restore_dir(index_dir);
+    // not tantivy_old, that one holds the index from before a migration
+    let old = index_dir.with_file_name("tantivy_pre_restore");
+    if !restore.exists() {
+        // left over if the app was closed right after the restore was moved in place
+        if old.exists() && index_dir.exists() {
+            std::fs::remove_dir_all(&old).infer_err()?;
+        }
+        return Ok(());
+    }
+    if old.exists() {
+        std::fs::remove_dir_all(&old).infer_err()?;
+    }
+    if index_dir.exists() {
+        std::fs::rename(index_dir, &old).infer_err()?;
+    }
+    std::fs::rename(&restore, index_dir).infer_err()?;
+    if old.exists() {
+        std::fs::remove_dir_all(&old).infer_err()?;
+    }
+    Ok(())
+}
+
+fn staged_restore_dir(index_dir: &Path) -> PathBuf {
+    index_dir.with_file_name("tantivy_restore")
+}
+
+// returns the timestamp of the snapshot
+fn create_snapshot(db: &AppDatabase, dirs: &BackupDirs) -> Result<u64, Error> {
+    let time = db.now_time()?;
+    let partial = dirs.backups.join(format!(".{}.partial", time));
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial).infer_err()?;
+    }
+    std::fs::create_dir_all(partial.join("tantivy")).infer_err()?;
+
+    {
+        // no commits can happen while the writer is locked, but merges can still
+        // garbage collect segment files that are being copied. so just try again
+        let mut writer = db.index_writer.write().infer_err()?;
+        let _opstamp = writer.commit().infer_err()?;
+        let mut tries = 0;
+        while let Err(e) = copy_index_files(db, &dirs.index, &partial.join("tantivy")) {
+            tries += 1;
+            if tries >= 3 {
+                return Err(e);
+            }
+        }
+    }
+
+    let manifest = walkdir::WalkDir::new(&dirs.images)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let meta = e.metadata().ok().filter(|m| m.is_file())?;
+            Some(ManifestEntry {
+                path: e.path().strip_prefix(&dirs.images).ok()?.to_path_buf(),
+                size: meta.len(),
+                mtime: meta
+                    .modified()
+                    .ok()?
+                    .duration_since(UNIX_EPOCH)
+                    .ok()?
+                    .as_secs(),
+            })
+        })
+        .collect::<Vec<_>>();
+    std::fs::write(
+        partial.join("images.json"),
+        serde_json::to_vec(&manifest).infer_err()?,
+    )
+    .infer_err()?;
+
+    std::fs::rename(&partial, dirs.backups.join(time.to_string())).infer_err()?;
+    Ok(time)
+}
+
+fn copy_index_files(db: &AppDatabase, from: &Path, to: &Path) -> Result<(), Error> {
+    let metas = db.load_metas()?;
+    for segment in metas.segments.iter() {
+        for file in segment.list_files() {
+            // the delete file is listed even if the segment has no deletes
+            if !segment.has_deletes() && file.extension().map(|e| e == "del").unwrap_or(false) {
+                continue;
+            }
+            let _ = std::fs::copy(from.join(&file), to.join(&file)).infer_err()?;
+        }
+    }
+    // written from the loaded metas so that it only points to the copied segments
+    std::fs::write(
+        to.join("meta.json"),
+        serde_json::to_vec_pretty(&metas).infer_err()?,
+    )
+    .infer_err()?;
+    Ok(())
+}
+
+// keeps the newest backup of each of the last KEEP_DAILY days and KEEP_WEEKLY weeks
+fn apply_retention(dirs: &BackupDirs) -> Result<(), Error> {
+    let mut backups = list_backups_in(dirs)?;
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for &t in backups.iter() {
+        if days.len() < KEEP_DAILY && days.insert(t / DAY) {
+            let _ = keep.insert(t);
+        }
+        if weeks.len() < KEEP_WEEKLY && weeks.insert(t / (DAY * 7)) {
+            let _ = keep.insert(t);
+        }
+    }
+
+    backups
+        .into_iter()
+        .filter(|t| !keep.contains(t))
+        .try_for_each(|t| std::fs::remove_dir_all(dirs.backups.join(t.to_string())).infer_err())
+}
+
+fn list_backups_in(dirs: &BackupDirs) -> Result<Vec<u64>, Error> {
+    if !dirs.backups.exists() {
+        return Ok(vec![]);
+    }
+    let backups = std::fs::read_dir(&dirs.backups)
+        .infer_err()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        // partial snapshots start with a '.' and don't parse
+        .filter_map(|e| e.file_name().to_str()?.parse().ok())
+        .collect();
+    Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_keeps_the_migration_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("tantivy");
+        let migrated = dir.path().join("tantivy_old");
+        for d in [&index, &migrated, &staged_restore_dir(&index)] {
+            std::fs::create_dir_all(d).unwrap();
+        }
+        std::fs::write(staged_restore_dir(&index).join("meta.json"), "{}").unwrap();
+
+        apply_staged_restore(&index).unwrap();
+        assert!(index.join("meta.json").exists());
+        assert!(migrated.exists());
+        assert!(!staged_restore_dir(&index).exists());
+        assert!(!index.with_file_name("tantivy_pre_restore").exists());
+    }
+}
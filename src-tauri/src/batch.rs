@@ -0,0 +1,403 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use kolekk_types::{
+    objects::{
+        BatchProgress, BatchResult, Fields, Group, Id, Meta, SearchableEntry, Taggable, TypeFacet,
+    },
+    utility::Path,
+};
+use serde_json::{Map, Value};
+use tantivy::{
+    collector::{DocSetCollector, TopDocs},
+    query::TermQuery,
+    schema::Facet,
+    schema::IndexRecordOption,
+    Document, IndexWriter, Term,
+};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    archive::delete_archive,
+    bad_error::{BadError, Error, InferBadError},
+    config::AppConfig,
+    database::{delete_object, AppDatabase, DbAble, FacetFrom, JsmlObject},
+    filesystem::get_path,
+};
+
+type Object = Meta<Taggable<SearchableEntry<Map<String, Value>>>, Facet>;
+type GroupObject = Meta<Taggable<SearchableEntry<Group>>, Facet>;
+
+// only selections larger than this get progress events
+const PROGRESS_THRESHOLD: usize = 100;
+const PROGRESS_INTERVAL: usize = 25;
+
+#[tauri::command]
+pub async fn add_tags_to_objects(
+    db: State<'_, AppDatabase>,
+    app: State<'_, AppHandle>,
+    ids: Vec<Id>,
+    tag_ids: Vec<Id>,
+) -> Result<Vec<BatchResult>, Error> {
+    update_objects(db.inner(), app.inner(), "add_tags", ids, |o| {
+        for t in tag_ids.iter() {
+            if !o.data.tags.contains(t) {
+                o.data.tags.push(*t);
+            }
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub async fn remove_tags_from_objects(
+    db: State<'_, AppDatabase>,
+    app: State<'_, AppHandle>,
+    ids: Vec<Id>,
+    tag_ids: Vec<Id>,
+) -> Result<Vec<BatchResult>, Error> {
+    update_objects(db.inner(), app.inner(), "remove_tags", ids, |o| {
+        o.data.tags.retain(|t| !tag_ids.contains(t));
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub async fn delete_objects(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+    ids: Vec<Id>,
+) -> Result<Vec<BatchResult>, Error> {
+    let db = db.inner();
+    let ids = dedup(ids);
+    let progress = Progress::new(app.inner(), "delete", ids.len());
+    let time = db.now_time()?;
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    let res = ids
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let r = db
+                .get_doc_address(id)
+                .map(|_| delete_object(db, &writer, id, time));
+            progress.update(i + 1);
+            BatchResult::new(id, r)
+        })
+        .collect::<Vec<_>>();
+    let _opstamp = writer.commit().infer_err()?;
+    for r in res.iter().filter(|r| r.error.is_none()) {
+        let _ = delete_archive(config.inner(), r.id).dbg();
+    }
+    Ok(res)
+}
+
+// removes the objects from any group they are in and appends them to the given group
+#[tauri::command]
+pub async fn move_objects_to_group(
+    db: State<'_, AppDatabase>,
+    app: State<'_, AppHandle>,
+    ids: Vec<Id>,
+    group_id: Id,
+) -> Result<Vec<BatchResult>, Error> {
+    let db = db.inner();
+    let ids = dedup(ids);
+    let progress = Progress::new(app.inner(), "move_to_group", ids.len());
+
+    let searcher = db.get_searcher();
+    let groups = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &TypeFacet::Group.facet()),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(10000),
+        )
+        .infer_err()?
+        .into_iter()
+        .map(|(_score, address)| {
+            let mut doc = searcher.doc(address).infer_err()?;
+            DbAble::take(db, &mut doc)
+        })
+        .collect::<Result<Vec<GroupObject>, Error>>()?;
+    let (mut target, groups): (Vec<_>, Vec<_>) = groups.into_iter().partition(|g| g.id == group_id);
+    let mut target = target.pop().bad_err("group does not exist")?;
+
+    let res = ids
+        .iter()
+        .enumerate()
+        .map(|(i, &id)| {
+            let r = if id == group_id {
+                None::<()>.bad_err("cannot move a group into itself")
+            } else {
+                db.get_doc_address(id).map(|_| ())
+            };
+            progress.update(i + 1);
+            BatchResult::new(id, r)
+        })
+        .collect::<Vec<_>>();
+    let moved = res
+        .iter()
+        .filter(|r| r.error.is_none())
+        .map(|r| r.id)
+        .collect::<HashSet<_>>();
+
+    let time = db.now_time()?;
+    let mut writer = db.index_writer.write().infer_err()?;
+    for mut g in groups {
+        if !g.data.data.data.items.iter().any(|i| moved.contains(i)) {
+            continue;
+        }
+        let group = &mut g.data.data.data;
+        group.items.retain(|i| !moved.contains(i));
+        if group.main.map(|m| moved.contains(&m)).unwrap_or(false) {
+            group.main = None;
+        }
+        g.last_update = time;
+        replace_object(db, &writer, g)?;
+    }
+    for id in ids.iter().filter(|id| moved.contains(id)) {
+        if !target.data.data.data.items.contains(id) {
+            target.data.data.data.items.push(*id);
+        }
+    }
+    target.last_update = time;
+    replace_object(db, &writer, target)?;
+    let _opstamp = writer.commit().infer_err()?;
+
+    Ok(res)
+}
+
+// writes the objects to a json file
+#[tauri::command]
+pub async fn export_objects(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+    ids: Vec<Id>,
+    path: Path,
+) -> Result<Vec<BatchResult>, Error> {
+    let db = db.inner();
+    let ids = dedup(ids);
+    let progress = Progress::new(app.inner(), "export", ids.len());
+
+    let mut file = BufWriter::new(File::create(get_path(&path, config.inner())).infer_err()?);
+    file.write_all(b"[").infer_err()?;
+    let mut first = true;
+    let mut res = vec![];
+    for (i, id) in ids.into_iter().enumerate() {
+        let obj = db.get_doc(id).and_then(|mut doc| {
+            let obj: JsmlObject = DbAble::take(db, &mut doc)?;
+            serde_json::to_vec(&obj).infer_err()
+        });
+        let r = match obj {
+            Ok(obj) => {
+                if !first {
+                    file.write_all(b",").infer_err()?;
+                }
+                first = false;
+                file.write_all(&obj).infer_err()?;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+        progress.update(i + 1);
+        res.push(BatchResult::new(id, r));
+    }
+    file.write_all(b"]").infer_err()?;
+    file.flush().infer_err()?;
+
+    Ok(res)
+}
+
+pub fn update_objects(
+    db: &AppDatabase,
+    app: &AppHandle,
+    operation: &'static str,
+    ids: Vec<Id>,
+    mut f: impl FnMut(&mut Object) -> Result<(), Error>,
+) -> Result<Vec<BatchResult>, Error> {
+    // the same id twice would make the second update delete the first one
+    let ids = dedup(ids);
+    let progress = Progress::new(app, operation, ids.len());
+    let time = db.now_time()?;
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    let res = ids
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let r = db.get_doc(id).and_then(|mut doc| {
+                let mut obj: Object = DbAble::take(db, &mut doc)?;
+                f(&mut obj)?;
+                obj.last_update = time;
+                replace_object(db, &writer, obj)
+            });
+            progress.update(i + 1);
+            BatchResult::new(id, r)
+        })
+        .collect();
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(res)
+}
+
+// for jobs that change a single object in the background (crawling, link checks,
+// archiving). the object is read, changed and committed while holding the writer, so
+// updates from jobs running at the same time don't overwrite each other. last_update is
+// bumped like for any other change, otherwise other devices would see the synced update
+// as no newer than the object they have and drop it
+pub fn update_object<T, R>(
+    db: &AppDatabase,
+    id: Id,
+    f: impl FnOnce(&mut Meta<T, TypeFacet>) -> Result<R, Error>,
+) -> Result<R, Error>
+where
+    Meta<T, TypeFacet>: DbAble,
+{
+    let mut writer = db.index_writer.write().infer_err()?;
+    let mut doc = db.get_doc(id)?;
+    let mut obj: Meta<T, TypeFacet> = DbAble::take(db, &mut doc)?;
+    let r = f(&mut obj)?;
+    obj.last_update = db.now_time()?;
+    let mut doc = Document::new();
+    obj.add(db, &mut doc)?;
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    // the next update of this object has to see this one
+    db.index_reader.reload().infer_err()?;
+    Ok(r)
+}
+
+pub fn objects_of_type<T: DbAble>(db: &AppDatabase, facet: TypeFacet) -> Result<Vec<T>, Error> {
+    let searcher = db.get_searcher();
+    searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &facet.facet()),
+                IndexRecordOption::Basic,
+            ),
+            &DocSetCollector,
+        )
+        .infer_err()?
+        .into_iter()
+        .map(|address| {
+            let mut doc = searcher.doc(address).infer_err()?;
+            DbAble::take(db, &mut doc)
+        })
+        .collect()
+}
+
+fn replace_object<T: DbAble>(
+    db: &AppDatabase,
+    writer: &IndexWriter,
+    obj: Meta<T, Facet>,
+) -> Result<(), Error> {
+    let id = obj.id;
+    let mut doc = Document::new();
+    obj.add(db, &mut doc)?;
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    Ok(())
+}
+
+fn dedup(ids: Vec<Id>) -> Vec<Id> {
+    let mut set = HashSet::new();
+    ids.into_iter().filter(|id| set.insert(*id)).collect()
+}
+
+trait BatchResultExt {
+    fn new(id: Id, r: Result<(), Error>) -> Self;
+}
+impl BatchResultExt for BatchResult {
+    fn new(id: Id, r: Result<(), Error>) -> Self {
+        Self {
+            id,
+            error: r.err().map(|e| e.0.into_owned()),
+        }
+    }
+}
+
+struct Progress<'a> {
+    app: &'a AppHandle,
+    operation: &'static str,
+    total: usize,
+}
+
+impl<'a> Progress<'a> {
+    fn new(app: &'a AppHandle, operation: &'static str, total: usize) -> Self {
+        Self {
+            app,
+            operation,
+            total,
+        }
+    }
+
+    fn update(&self, done: usize) {
+        if self.total < PROGRESS_THRESHOLD || (done % PROGRESS_INTERVAL != 0 && done != self.total)
+        {
+            return;
+        }
+        let _ = self
+            .app
+            .emit_all(
+                "batch-progress",
+                BatchProgress {
+                    operation: self.operation.to_owned(),
+                    done,
+                    total: self.total,
+                },
+            )
+            .infer_err()
+            .dbg();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        tests::{add_object, commit, test_db, test_object},
+        RObject,
+    };
+
+    #[tokio::test]
+    async fn concurrent_updates_are_not_lost() {
+        let (_dir, db) = test_db().await;
+        let obj = test_object(&db, "", vec![], 0);
+        let id = obj.id;
+        add_object(&db, obj);
+        commit(&db);
+
+        std::thread::scope(|s| {
+            for key in ["link", "archive"] {
+                let db = &db;
+                let _ = s.spawn(move || {
+                    for i in 0..20 {
+                        update_object(db, id, |o: &mut RObject<Map<String, Value>>| {
+                            let _ = o
+                                .data
+                                .data
+                                .data
+                                .insert(format!("{}{}", key, i), Value::Null);
+                            Ok(())
+                        })
+                        .unwrap();
+                    }
+                });
+            }
+        });
+
+        let obj: RObject<Map<String, Value>> =
+            DbAble::take(&db, &mut db.get_doc(id).unwrap()).unwrap();
+        assert_eq!(obj.data.data.data.len(), 40);
+        assert_ne!(obj.last_update, 0);
+    }
+}
@@ -1,193 +1,87 @@
 #[allow(unused_imports)]
 use crate::{dbg, debug, error};
 
-use std::{
-    collections::HashSet, fs, os::unix::fs::MetadataExt, path::PathBuf, str::FromStr,
-    time::Duration,
-};
+use std::{collections::HashSet, path::PathBuf, str::FromStr, time::Duration};
 
 use futures::{future::OptionFuture, stream::FuturesUnordered, StreamExt};
 use kolekk_types::{
     objects::{
-        Bookmark, BookmarkSource, Fields, Indexed, Meta, SearchableEntry, Taggable, Tagged,
+        Bookmark, BookmarkSourceFormat, SearchFilters, SourceRefreshReport, Taggable, Tagged,
         TypeFacet, WithContext,
     },
     utility::{ByteArrayFile, DragDropPaste},
 };
 use reqwest::Client;
 use serde_json::Value;
-use tantivy::{
-    collector::TopDocs,
-    query::{Occur, TermQuery},
-    schema::IndexRecordOption,
-    Document, Term,
-};
-use tauri::{http::Uri, State};
-use tokio::{fs::File, io::AsyncReadExt};
+use tantivy::query::Occur;
+use tauri::{http::Uri, AppHandle, State};
 
 use crate::{
     bad_error::{Error, InferBadError, Inspectable},
     config::AppConfig,
-    database::{add_or_search_tag, AppDatabase, AutoDbAble, DbAble, FacetFrom, IntoRObject, ObjectSearchScoreTweaker},
+    crawl::{add_snippets, parse_page},
+    database::{AppDatabase, AutoDbAble, ObjectSearchScoreTweaker},
     filesystem::get_path,
+    sources::{
+        bookmark_sources, import_source, parsers::detect_format, refresh_source, source_modified,
+    },
+    watch::SourceWatcher,
 };
 
 #[tauri::command]
 pub async fn refresh_bookmark_sources(
     db: State<'_, AppDatabase>,
     config: State<'_, AppConfig>,
-    client: State<'_, Client>,
-) -> Result<(), Error> {
-    let dbi = db.inner();
-    let searcher = db.get_searcher();
-    let obj_type_query = TermQuery::new(
-        Term::from_facet(
-            db.get_field(Fields::Type),
-            &TypeFacet::BookmarkSource.facet(),
-        ),
-        IndexRecordOption::Basic,
-    );
-    let sources: Result<Vec<Meta<BookmarkSource, TypeFacet>>, _> = searcher
-        .search(&obj_type_query, &TopDocs::with_limit(10000))
-        .infer_err()?
-        .into_iter()
-        .map(move |(_score, address)| {
-            let mut doc = searcher.doc(address).infer_err()?;
-            DbAble::take(dbi, &mut doc).look(|e| dbg!((_score, e)))
-        })
-        .collect();
-    let sources = sources.infer_err()?;
-
-    for source in sources {
-        let mdata = fs::metadata(get_path(&source.data.path, config.inner())).infer_err()?;
-        if source.data.mtime == mdata.mtime() {
+    app: State<'_, AppHandle>,
+    watcher: State<'_, SourceWatcher>,
+) -> Result<Vec<SourceRefreshReport>, Error> {
+    let _lock = watcher.refreshing.lock().await;
+    let mut reports = vec![];
+    for source in bookmark_sources(db.inner())? {
+        if !source_modified(&source, config.inner()) {
             continue;
         }
-
-        {
-            let mut writer = db.index_writer.write().infer_err()?;
-            writer.delete_term(Term::from_field_u64(
-                db.get_field(Fields::SourceId),
-                source.id as _,
-            ));
-            writer.commit().infer_err()?;
-            db.index_reader.reload().infer_err()?;
-        }
-        add_bookmark_source(
-            db.clone(),
-            config.clone(),
-            client.clone(),
-            source.data.title,
-            source.data.path,
-        )
-        .await?;
+        reports.push(refresh_source(app.inner(), source)?);
     }
-
-    Ok(())
+    Ok(reports)
 }
 
 #[tauri::command]
 pub async fn add_bookmark_source(
-    db: State<'_, AppDatabase>,
     config: State<'_, AppConfig>,
-    client: State<'_, Client>,
+    app: State<'_, AppHandle>,
+    watcher: State<'_, SourceWatcher>,
     title: String,
     path: kolekk_types::utility::Path,
+    // detected from the file name if not given
+    format: Option<BookmarkSourceFormat>,
 ) -> Result<u32, Error> {
-    let db = db.inner();
     let pb = get_path(&path, config.inner());
-
-    let mut file = File::open(&pb).await.infer_err()?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).await.infer_err()?;
-    let mdata = file.metadata().await.infer_err()?;
-    let source_id = db.new_id();
-    let time = db.now_time().infer_err()?;
-    let source = Meta {
-        data: SearchableEntry {
-            searchable: vec![
-                Indexed {
-                    field: Fields::Text,
-                    data: title.clone().into(),
-                },
-                Indexed {
-                    field: Fields::Text,
-                    data: pb.to_string_lossy().to_string().into(),
-                },
-            ],
-            data: BookmarkSource {
-                title: title.clone(),
-                path,
-                last_checked: time,
-                mtime: mdata.mtime(),
-            },
-        },
-        facet: TypeFacet::BookmarkSource,
-        ctime: time,
-        last_update: time,
-        last_interaction: time,
-        id: source_id,
-    };
-
-    let mut res = _get_tagged_bookmarks_from_text(&contents, client.inner())
-        .await
-        .infer_err()?;
-    for bk in res.0.iter_mut() {
-        bk.data.source = Some(source_id);
-        bk.tags.push(title.clone());
-    }
-
-    {
-        let mut writer = db.index_writer.write().infer_err()?;
-        let _opstamp = writer.delete_term(Term::from_field_u64(
-            db.get_field(Fields::Id),
-            source_id as _,
-        ));
-    }
-    let mut doc = Document::new();
-    source.add(db, &mut doc)?;
-
-    for bk in res.0 {
-        // TODO: tags are duplicated as searcher cannot find tags that are added in this same commit
-        let tags: Result<Vec<_>, _> = bk.tags.iter().cloned().map(|t| add_or_search_tag(db, t)).collect();
-        let tags = tags?;
-        // TODO: BAD: OOF: IntoRObject trait implementations lock the writer. which is bad. write better code
-        let mut bk = bk.into_robject(db)?;
-        bk.data.data.tags = tags.into_iter().map(|t| t.id).collect();
-        let id = bk.id;
-        let mut doc = Document::new();
-        bk.add(db, &mut doc)?;
-
-        {
-            let mut writer = db.index_writer.write().infer_err()?;
-            let _opstamp =
-                writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
-            writer.add_document(doc).infer_err()?;
-        }
-    }
-
-    let mut writer = db.index_writer.write().infer_err()?;
-    let _opstamp = writer.add_document(doc).infer_err()?;
-    let _opstamp = writer.commit().infer_err()?;
-
-    Ok(source_id)
+    let format = format.unwrap_or_else(|| detect_format(&pb));
+    let report = import_source(app.inner(), title, path, format, None)?;
+    watcher.watch(&pb)?;
+    Ok(report.source)
 }
 
 #[tauri::command]
 pub async fn search_bookmarks(
     db: State<'_, AppDatabase>,
     query: String,
+    filters: Option<SearchFilters>,
     limit: usize,
     offset: usize,
 ) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, Error> {
-    crate::database::tagged_search(
+    let mut bookmarks = crate::database::tagged_search(
         db.inner(),
         TypeFacet::Bookmark,
-        query,
+        &query,
+        &filters.unwrap_or_default(),
         limit,
         offset,
         ObjectSearchScoreTweaker::new(db.inner())?,
-    )
+    )?;
+    add_snippets(db.inner(), &query, &mut bookmarks)?;
+    Ok(bookmarks)
 }
 
 #[tauri::command]
@@ -309,17 +203,6 @@ pub fn tagged_strings_from_text(
     let mut potential_bks = vec![];
     let mut donno = vec![];
 
-    let get_tags = |tag: &str| {
-        let new_tags = tag
-            .split('+')
-            .flat_map(|t| t.trim().split('/'))
-            .map(|t| t.trim())
-            .filter(|t| !t.is_empty())
-            .map(ToOwned::to_owned)
-            .collect();
-        new_tags
-    };
-
     for line in text.lines().filter(|l| !l.trim().is_empty()) {
         let mut indent = 0;
         for c in line.chars() {
@@ -343,7 +226,7 @@ pub fn tagged_strings_from_text(
         ) {
             (true, _, _, _) | (false, true, false, false) => {
                 let tag = line.trim_start_matches(['#', ' ', '-']).trim_end();
-                tags.push((indent, get_tags(tag)));
+                tags.push((indent, split_tags(tag)));
             }
             (false, _, true, _) | (false, _, false, true) => {
                 potential_bks.push(Tagged {
@@ -446,32 +329,90 @@ pub fn get_urls_from_hrefs(mut h: &str) -> HashSet<&str> {
     urls
 }
 
+// - [title](url). '\' escapes ascii punctuation in the title and the url (see
+// export::bookmark_line)
 pub fn bookmark_from_markdown_url(u: impl AsRef<str>) -> Option<Bookmark> {
-    let u = u.as_ref();
-    if u.starts_with("- [") {
-        let title_start = u.find('[').unwrap();
-        if let Some(title_end) = u.find("](") {
-            let url_start = u[title_end..].find('(');
-            let url_end = u[title_end..].find(')');
-            if let (Some(start), Some(end)) = (url_start, url_end) {
-                let url = &u[title_end..][start + 1..end];
-                let _ = Uri::from_str(url).ok()?;
-                let b = Bookmark {
-                    url: url.into(),
-                    title: Some(u[title_start + 1..title_end].into()),
-                    description: None,
-                    source: None,
-                };
-                return Some(b);
+    let rest = u.as_ref().strip_prefix("- [")?;
+    let title_end = find_unescaped(rest, "](")?;
+    let url_part = &rest[title_end + 2..];
+    let url_end = find_unescaped(url_part, ")")?;
+    let url = unescape_markdown(&url_part[..url_end]);
+    let _ = Uri::from_str(&url).ok()?;
+    let b = Bookmark {
+        url,
+        title: Some(unescape_markdown(&rest[..title_end])).filter(|t| !t.is_empty()),
+        description: None,
+        source: None,
+        link: None,
+        archive: None,
+        crawled: None,
+        image: None,
+        icon: None,
+        site_name: None,
+        canonical: None,
+        source_tags: None,
+    };
+    Some(b)
+}
+
+// tags in a heading are separated by unescaped '+' and '/'
+fn split_tags(heading: &str) -> Vec<String> {
+    let mut tags = vec![];
+    // (char, escaped). escaped whitespace is not trimmed
+    let mut tag = Vec::<(char, bool)>::new();
+    let mut finish = |tag: &mut Vec<(char, bool)>| {
+        let start = tag.iter().position(|&(c, e)| e || !c.is_whitespace());
+        let end = tag.iter().rposition(|&(c, e)| e || !c.is_whitespace());
+        if let (Some(start), Some(end)) = (start, end) {
+            tags.push(tag[start..=end].iter().map(|&(c, _)| c).collect::<String>());
+        }
+        tag.clear();
+    };
+    let mut chars = heading.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().map(|c| c.is_ascii_punctuation()).unwrap_or(false) => {
+                tag.push((chars.next().expect("peeked"), true));
             }
+            '+' | '/' => finish(&mut tag),
+            c => tag.push((c, false)),
+        }
+    }
+    finish(&mut tag);
+    tags
+}
+
+// byte index of the first occurrence of pat that does not start with an escaped char
+fn find_unescaped(s: &str, pat: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if !escaped && s[i..].starts_with(pat) {
+            return Some(i);
         }
+        escaped = !escaped && c == '\\';
     }
     None
 }
 
+// like in commonmark, a '\' before anything other than ascii punctuation is just a '\'
+pub fn unescape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(n) if c == '\\' && n.is_ascii_punctuation() => {
+                out.push(*n);
+                let _ = chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
 pub async fn bookmark_from_url(u: String, client: &Client) -> Result<Bookmark, Error> {
     let _ = Uri::from_str(&u).infer_err()?;
-    let title: OptionFuture<_> = client
+    let page: OptionFuture<_> = client
         .get(&u)
         .send()
         .await
@@ -485,28 +426,31 @@ pub async fn bookmark_from_url(u: String, client: &Client) -> Result<Bookmark, E
                 .unwrap_or_default()
                 .contains("text/html")
             {
+                let url = page.url().clone();
                 page.text()
                     .await
                     .look(|e| dbg!(e))
                     .ok()
-                    .as_ref()
-                    .and_then(|t| {
-                        scraper::Html::parse_document(t)
-                            .select(&scraper::Selector::parse("title").ok()?)
-                            .next()
-                            .map(|e| e.inner_html())
-                            .look(|e| dbg!(e))
-                    })
+                    .and_then(|t| parse_page(&t, &url).look(|e| dbg!(e)).ok())
             } else {
                 None
             }
         })
         .into();
+    let page = page.await.flatten().unwrap_or_default();
     let b = Bookmark {
         url: u,
-        title: title.await.unwrap_or_default(),
-        description: None,
+        title: page.title,
+        description: page.description,
         source: None,
+        link: None,
+        archive: None,
+        crawled: None,
+        image: page.image,
+        icon: page.icon,
+        site_name: page.site_name,
+        canonical: page.canonical,
+        source_tags: None,
     };
     Ok(b)
 }
@@ -0,0 +1,359 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - the readable text of bookmarked pages is indexed in Fields::Body. the main element of
+//   the page (article, main, .. or body) is used, without navigation, scripts and such.
+// - title, description, preview image, favicon, .. are taken from OpenGraph / twitter card
+//   meta tags. the preview image and the favicon are handed to the Thumbnailer.
+// - new bookmarks are crawled in the background as soon as they are added. pages that were
+//   crawled a while back (or never) get crawled again with refresh_bookmark_content.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use kolekk_types::{
+    objects::{BatchProgress, BodySnippet, Bookmark, Fields, Id, Indexed, TypeFacet},
+    utility::ThumbnailSize,
+};
+use reqwest::{Client, Url};
+use scraper::{ElementRef, Html, Selector};
+use serde_json::{Map, Value};
+use tantivy::SnippetGenerator;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    bad_error::{BadError, Error, InferBadError},
+    batch::{objects_of_type, update_object},
+    database::{body_query, AppDatabase, DbAble, RObject},
+    images::thumbnails::Thumbnailer,
+    urls::{bookmark_key, set_key, tracking_params},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+const CRAWLS_IN_PARALLEL: usize = 8;
+// pages older than this are crawled again by default
+const DEFAULT_MAX_AGE: u64 = 60 * 60 * 24 * 30;
+// the rest of the text of huge pages is not indexed
+const MAX_BODY_LEN: usize = 100_000;
+const SNIPPET_LEN: usize = 200;
+
+#[derive(Default)]
+pub struct PageContent {
+    pub title: Option<String>,
+    pub body: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub icon: Option<String>,
+    pub site_name: Option<String>,
+    pub canonical: Option<String>,
+}
+
+// crawls bookmarks that were never crawled or were crawled more than max_age seconds ago.
+// returns the number of pages that were indexed
+#[tauri::command]
+pub async fn refresh_bookmark_content(
+    db: State<'_, AppDatabase>,
+    client: State<'_, Client>,
+    app: State<'_, AppHandle>,
+    max_age: Option<u64>,
+) -> Result<usize, Error> {
+    let db = db.inner();
+    let now = db.now_time()?;
+    let max_age = max_age.unwrap_or(DEFAULT_MAX_AGE);
+    let ids = stale_bookmarks(db, now.saturating_sub(max_age))?;
+    crawl_all(db, client.inner(), app.inner(), ids).await
+}
+
+// called with the ids of newly added bookmarks
+pub fn crawl_on_add(app: &AppHandle, ids: Vec<Id>) {
+    if ids.is_empty() {
+        return;
+    }
+    let handle = app.app_handle();
+    let _ = tokio::task::spawn(async move {
+        let _ = crawl_all(
+            handle.state::<AppDatabase>().inner(),
+            handle.state::<Client>().inner(),
+            &handle,
+            ids,
+        )
+        .await
+        .dbg();
+    });
+}
+
+// adds a "snippet" (BodySnippet) to the objects whose Fields::Body matched the query
+pub fn add_snippets(
+    db: &AppDatabase,
+    query: &str,
+    objects: &mut [Map<String, Value>],
+) -> Result<(), Error> {
+    if db.tokenize(query).is_empty() {
+        return Ok(());
+    }
+    let searcher = db.get_searcher();
+    let mut generator = SnippetGenerator::create(
+        &searcher,
+        &body_query(db, query),
+        db.get_field(Fields::Body),
+    )
+    .infer_err()?;
+    generator.set_max_num_chars(SNIPPET_LEN);
+    for o in objects.iter_mut() {
+        let Some(id) = o.get("id").and_then(Value::as_u64) else {
+            continue;
+        };
+        let doc = db.get_doc(id as _)?;
+        let snippet = generator.snippet_from_doc(&doc);
+        if snippet.is_empty() {
+            continue;
+        }
+        let snippet = BodySnippet {
+            fragment: snippet.fragment().to_string(),
+            highlighted: snippet.highlighted().iter().map(|h| h.bounds()).collect(),
+        };
+        let _ = o.insert("snippet".into(), serde_json::to_value(snippet).infer_err()?);
+    }
+    Ok(())
+}
+
+async fn crawl_all(
+    db: &AppDatabase,
+    client: &Client,
+    app: &AppHandle,
+    ids: Vec<Id>,
+) -> Result<usize, Error> {
+    let total = ids.len();
+    let done = AtomicUsize::new(0);
+    let crawled = futures::stream::iter(ids)
+        .map(|id| {
+            let done = &done;
+            async move {
+                let r = match crawl_bookmark(db, client, id).await.dbg() {
+                    Ok(bk) => {
+                        prefetch_thumbnails(app, &bk).await;
+                        true
+                    }
+                    Err(_) => false,
+                };
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                let _ = app
+                    .emit_all(
+                        "batch-progress",
+                        BatchProgress {
+                            operation: "crawl_bookmarks".into(),
+                            done,
+                            total,
+                        },
+                    )
+                    .infer_err()
+                    .dbg();
+                r
+            }
+        })
+        .buffer_unordered(CRAWLS_IN_PARALLEL)
+        .filter(|r| futures::future::ready(*r))
+        .count()
+        .await;
+    Ok(crawled)
+}
+
+// so that bookmark cards don't have to wait for the downloads
+async fn prefetch_thumbnails(app: &AppHandle, bk: &Bookmark) {
+    let thumbnailer = app.state::<Thumbnailer>();
+    for (uri, size) in [
+        (&bk.image, ThumbnailSize::W350),
+        (&bk.icon, ThumbnailSize::Original),
+    ] {
+        if let Some(uri) = uri {
+            let _ = thumbnailer.image_thumbnail(size, uri.clone()).await.dbg();
+        }
+    }
+}
+
+async fn crawl_bookmark(db: &AppDatabase, client: &Client, id: Id) -> Result<Bookmark, Error> {
+    let mut doc = db.get_doc(id)?;
+    let bk: RObject<Bookmark> = DbAble::take(db, &mut doc)?;
+    let url = Url::parse(&bk.data.data.data.url).infer_err()?;
+
+    let page = client
+        .get(url)
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .infer_err()?
+        .error_for_status()
+        .infer_err()?;
+    let is_html = page
+        .headers()
+        .get("content-type")
+        .and_then(|c| c.to_str().ok())
+        .map(|c| c.contains("text/html"))
+        .unwrap_or(false);
+    let page = if is_html {
+        // relative urls in the page are relative to where the redirects ended up
+        let url = page.url().clone();
+        parse_page(&page.text().await.infer_err()?, &url)?
+    } else {
+        PageContent::default()
+    };
+
+    let tracking = tracking_params(db)?;
+    let crawled = db.now_time()?;
+    // the bookmark might have been edited while the page was downloading
+    update_object(db, id, |bk: &mut RObject<Bookmark>| {
+        bk.data
+            .searchable
+            .retain(|i| !matches!(i.field, Fields::Body));
+        if !page.body.is_empty() {
+            bk.data.searchable.push(Indexed {
+                field: Fields::Body,
+                data: page.body.into(),
+            });
+        }
+        let data = &mut bk.data.data.data;
+        // things the user might have edited are only filled if missing
+        for (field, value) in [
+            (&mut data.title, page.title),
+            (&mut data.description, page.description),
+        ] {
+            if let (true, Some(v)) = (field.is_none(), value) {
+                bk.data.searchable.push(Indexed {
+                    field: Fields::Text,
+                    data: v.clone().into(),
+                });
+                *field = Some(v);
+            }
+        }
+        data.image = page.image;
+        data.icon = page.icon;
+        data.site_name = page.site_name;
+        data.canonical = page.canonical;
+        data.crawled = Some(crawled);
+        let bookmark = data.clone();
+        // the canonical url might make this a duplicate of another bookmark
+        let key = bookmark_key(&bookmark.url, bookmark.canonical.as_deref(), &tracking);
+        set_key(&mut bk.data.searchable, key);
+        Ok(bookmark)
+    })
+}
+
+fn stale_bookmarks(db: &AppDatabase, crawled_before: u64) -> Result<Vec<Id>, Error> {
+    let bookmarks: Vec<RObject<Bookmark>> = objects_of_type(db, TypeFacet::Bookmark)?;
+    let stale = bookmarks
+        .into_iter()
+        .filter(|bk| {
+            bk.data
+                .data
+                .data
+                .crawled
+                .map(|c| c < crawled_before)
+                .unwrap_or(true)
+        })
+        .map(|bk| bk.id)
+        .collect();
+    Ok(stale)
+}
+
+// url is used to resolve relative links in the page
+pub fn parse_page(html: &str, url: &Url) -> Result<PageContent, Error> {
+    let doc = Html::parse_document(html);
+    let mut body = String::new();
+    for e in main_content(&doc)? {
+        let text = e.text().flat_map(str::split_whitespace).collect::<Vec<_>>();
+        if text.is_empty() {
+            continue;
+        }
+        body.push_str(&text.join(" "));
+        body.push('\n');
+        if body.len() > MAX_BODY_LEN {
+            let mut end = MAX_BODY_LEN;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+            break;
+        }
+    }
+
+    let meta = |names: &[&str]| {
+        names.iter().find_map(|n| {
+            let s = selector(&format!("meta[property=\"{0}\"], meta[name=\"{0}\"]", n)).ok()?;
+            doc.select(&s)
+                .filter_map(|e| e.value().attr("content"))
+                .map(str::trim)
+                .find(|c| !c.is_empty())
+                .map(String::from)
+        })
+    };
+    let link = |rels: &[&str]| {
+        rels.iter().find_map(|r| {
+            let s = selector(&format!("link[rel~=\"{}\"][href]", r)).ok()?;
+            let href = doc.select(&s).find_map(|e| e.value().attr("href"))?;
+            absolute_url(url, href)
+        })
+    };
+
+    Ok(PageContent {
+        title: meta(&["og:title", "twitter:title"]).or_else(|| page_title(&doc)),
+        body,
+        description: meta(&["og:description", "twitter:description", "description"]),
+        image: meta(&[
+            "og:image",
+            "og:image:url",
+            "twitter:image",
+            "twitter:image:src",
+        ])
+        .and_then(|i| absolute_url(url, &i)),
+        icon: link(&["icon", "apple-touch-icon"]).or_else(|| absolute_url(url, "/favicon.ico")),
+        site_name: meta(&["og:site_name"]),
+        canonical: link(&["canonical"])
+            .or_else(|| meta(&["og:url"]).and_then(|u| absolute_url(url, &u))),
+    })
+}
+
+fn absolute_url(base: &Url, url: &str) -> Option<String> {
+    let url = base.join(url.trim()).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+pub fn page_title(doc: &Html) -> Option<String> {
+    let title = doc
+        .select(&selector("title").ok()?)
+        .next()?
+        .text()
+        .collect::<String>();
+    Some(title.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+// the outermost headings, paragraphs, lists and images of the main element of the page
+pub fn main_content(doc: &Html) -> Result<Vec<ElementRef<'_>>, Error> {
+    let mut root = None;
+    for s in ["article", "main", "[role=main]", "body"] {
+        root = doc.select(&selector(s)?).next();
+        if root.is_some() {
+            break;
+        }
+    }
+    let root = root.bad_err("page has no body")?;
+
+    let content = selector("h1, h2, h3, h4, h5, h6, p, pre, blockquote, ul, ol, img, figure")?;
+    let elements = root
+        .select(&content)
+        .filter(|e| {
+            // nested elements are already part of their outermost match
+            !e.ancestors()
+                .take_while(|a| a.id() != root.id())
+                .filter_map(ElementRef::wrap)
+                .any(|a| content.matches(&a))
+        })
+        .collect();
+    Ok(elements)
+}
+
+pub fn selector(s: &str) -> Result<Selector, Error> {
+    Selector::parse(s).ok().bad_err("bad selector")
+}
@@ -4,54 +4,59 @@ use crate::{dbg, debug, error};
 use std::{
     collections::{hash_map::RandomState, HashMap, HashSet},
     fmt::Debug,
-    ops::Deref,
+    ops::{Bound, Deref},
     sync::{atomic::AtomicU32, Arc, RwLock},
 };
 
 use kolekk_types::{
     objects::{
-        Bookmark, BookmarkSource, Fields, Id, Image, Indexed, Meta, SearchableEntry, Tag, Taggable,
-        Tagged, TypeFacet,
+        Bookmark, BookmarkSource, Fields, Group, Id, Image, Indexed, Meta, SavedSearch,
+        SearchFilters, SearchSort, SearchableEntry, Tag, Taggable, Tagged, TypeFacet,
     },
     utility::Path,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use tantivy::{
-    collector::{ScoreSegmentTweaker, ScoreTweaker, TopDocs},
+    collector::{DocSetCollector, ScoreSegmentTweaker, ScoreTweaker, TopDocs},
     directory::{ManagedDirectory, MmapDirectory},
     fastfield::Column,
     query::{
-        AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhraseQuery, QueryParser,
-        TermQuery,
+        AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, MoreLikeThisQuery, Occur, PhraseQuery,
+        Query, QueryParser, RangeQuery, TermQuery,
     },
-    schema::{Facet, FacetOptions, Field, IndexRecordOption, FAST, INDEXED, STORED, TEXT},
-    DocAddress, Document, Index, IndexReader, IndexWriter, SegmentReader, Term,
+    schema::{
+        Cardinality, Facet, FacetOptions, Field, IndexRecordOption, NumericOptions, Schema, FAST,
+        INDEXED, STORED, STRING,
+    },
+    DocAddress, Document, Index, IndexMeta, IndexReader, IndexWriter, SegmentReader, Term,
 };
 use tauri::{AppHandle, Manager, State, WindowEvent};
 
 use crate::{
+    backup::apply_staged_restore,
     bad_error::{BadError, Error, InferBadError, Inspectable},
     config::AppConfig,
-    tag::_get_tags_from_ids,
+    sync::SyncLog,
+    tag::{_get_tags_from_ids, tag_searchables},
+    tokenizer::{is_cjk, prefix_options, register_tokenizers, text_options, TEXT_TOKENIZER},
+    urls::{
+        bookmark_key, default_tracking_params, find_bookmark, get_key, set_bookmark_key,
+        tracking_params,
+    },
 };
 
-#[tauri::command]
-pub fn new_temp_facet() -> String {
-    let id = uuid::Uuid::new_v4();
-    let uuid = format!("/temp/{}", id.hyphenated());
-    uuid
-}
-
 #[tauri::command]
 pub async fn delete_from_id(
     db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
     id: u64,
 ) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
     let t = delete_item(db.inner(), id)?;
 
     let mut writer = db.index_writer.write().infer_err()?;
     let _opstamp = writer.commit().infer_err()?;
+    let _ = crate::archive::delete_archive(config.inner(), id as _).dbg();
 
     Ok(t)
 }
@@ -82,8 +87,31 @@ pub async fn delete_facet_objects(
     db: State<'_, AppDatabase>,
     facet: TypeFacet,
 ) -> Result<(), Error> {
+    if matches!(facet, TypeFacet::Internal(_)) {
+        return None.bad_err("internal facets can't be deleted");
+    }
     let mut writer = db.index_writer.write().infer_err()?;
-    let _opstamp = writer.delete_term(Term::from_facet(db.get_field(Fields::Type), &facet.facet()));
+    // temp objects are never synced, so their deletions don't have to be logged
+    if matches!(facet, TypeFacet::Temp(_)) {
+        let _opstamp =
+            writer.delete_term(Term::from_facet(db.get_field(Fields::Type), &facet.facet()));
+    } else {
+        let time = db.now_time()?;
+        let searcher = db.get_searcher();
+        let query = TermQuery::new(
+            Term::from_facet(db.get_field(Fields::Type), &facet.facet()),
+            IndexRecordOption::Basic,
+        );
+        for address in searcher.search(&query, &DocSetCollector).infer_err()? {
+            let doc = searcher.doc(address).infer_err()?;
+            if let Some(id) = doc
+                .get_first(db.get_field(Fields::Id))
+                .and_then(|v| v.as_u64())
+            {
+                delete_object(db.inner(), &writer, id as _, time);
+            }
+        }
+    }
     let _opstamp = writer.commit().infer_err()?;
     Ok(())
 }
@@ -91,15 +119,33 @@ pub async fn delete_facet_objects(
 #[tauri::command]
 pub async fn enter_searchable(
     db: State<'_, AppDatabase>,
+    app: State<'_, AppHandle>,
     data: Vec<SearchableEntry<serde_json::Map<String, serde_json::Value>>>,
     facet: TypeFacet,
 ) -> Result<(), Error> {
     let ctime = db.now_time().infer_err()?;
+    let tracking = match facet {
+        TypeFacet::Bookmark => Some(tracking_params(db.inner())?),
+        _ => None,
+    };
     let writer = db.index_writer.read().infer_err()?;
-    data.into_iter().try_for_each(|e| {
+    let mut ids = vec![];
+    let mut keys = HashSet::new();
+    data.into_iter().try_for_each(|mut e| {
+        if let Some(tracking) = tracking.as_ref() {
+            set_bookmark_key(&mut e, tracking);
+            // urls that are already bookmarked are not added again
+            if let Some(key) = get_key(&e.searchable) {
+                if !keys.insert(key.to_owned()) || find_bookmark(db.inner(), key)?.is_some() {
+                    return Ok(());
+                }
+            }
+        }
         let mut doc = Document::new();
+        let id = db.new_id();
+        ids.push(id);
         let v = Meta {
-            id: db.new_id(),
+            id,
             facet: facet.clone(),
             data: Taggable {
                 data: e,
@@ -117,16 +163,30 @@ pub async fn enter_searchable(
     })?;
     drop(writer);
     let _opstamp = db.index_writer.write().infer_err()?.commit().infer_err()?;
+    if matches!(facet, TypeFacet::Bookmark) {
+        crate::crawl::crawl_on_add(app.inner(), ids.clone());
+        crate::archive::archive_on_add(app.inner(), ids)?;
+    }
     Ok(())
 }
 
 #[tauri::command]
 pub async fn enter_searchable_item(
     db: State<'_, AppDatabase>,
-    data: SearchableEntry<serde_json::Map<String, serde_json::Value>>,
+    app: State<'_, AppHandle>,
+    mut data: SearchableEntry<serde_json::Map<String, serde_json::Value>>,
     facet: TypeFacet,
 ) -> Result<u32, Error> {
     let ctime = db.now_time().infer_err()?;
+    if matches!(facet, TypeFacet::Bookmark) {
+        set_bookmark_key(&mut data, &tracking_params(db.inner())?);
+        // the url is already bookmarked
+        if let Some(key) = get_key(&data.searchable) {
+            if let Some(bk) = find_bookmark(db.inner(), key)? {
+                return Ok(bk.id);
+            }
+        }
+    }
     let writer = db.index_writer.read().infer_err()?;
 
     let id = db.new_id();
@@ -145,16 +205,21 @@ pub async fn enter_searchable_item(
 
     drop(writer);
     let _opstamp = db.index_writer.write().infer_err()?.commit().infer_err()?;
+    if matches!(facet, TypeFacet::Bookmark) {
+        crate::crawl::crawl_on_add(app.inner(), vec![id]);
+        crate::archive::archive_on_add(app.inner(), vec![id])?;
+    }
     Ok(id)
 }
 
-type JsmlObject = Meta<Taggable<serde_json::Map<String, serde_json::Value>>, TypeFacet>;
+pub type JsmlObject = Meta<Taggable<serde_json::Map<String, serde_json::Value>>, TypeFacet>;
 
 #[tauri::command]
 pub async fn search_jsml_object(
     db: State<'_, AppDatabase>,
     query: String,
     facet: TypeFacet,
+    filters: Option<SearchFilters>,
     limit: usize,
     offset: usize,
 ) -> Result<Vec<JsmlObject>, Error> {
@@ -162,6 +227,23 @@ pub async fn search_jsml_object(
         db.inner(),
         facet,
         query,
+        &filters.unwrap_or_default(),
+        limit,
+        offset,
+        ObjectSearchScoreTweaker::new(db.inner())?,
+    )
+}
+
+#[tauri::command]
+pub async fn search_related_objects(
+    db: State<'_, AppDatabase>,
+    id: Id,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<JsmlObject>, Error> {
+    related_search(
+        db.inner(),
+        id,
         limit,
         offset,
         ObjectSearchScoreTweaker::new(db.inner())?,
@@ -179,6 +261,7 @@ pub async fn add_tag_to_object(
     let mut v: Meta<Taggable<SearchableEntry<serde_json::Map<String, serde_json::Value>>>, Facet> =
         DbAble::take(db.inner(), &mut doc)?;
     v.data.tags.push(tag_id);
+    v.last_update = db.now_time()?;
     let mut doc = Document::new();
     v.add(db.inner(), &mut doc)?;
 
@@ -200,6 +283,7 @@ pub async fn remove_tag_from_object(
     let mut j: Meta<Taggable<SearchableEntry<serde_json::Map<String, serde_json::Value>>>, Facet> =
         DbAble::take(db.inner(), &mut doc)?;
     j.data.tags.retain(|&t| t != tag_id);
+    j.last_update = db.now_time()?;
     let mut doc = Document::new();
     j.add(db.inner(), &mut doc)?;
 
@@ -227,19 +311,23 @@ impl IntoRObject for Bookmark {
     type Ctx = AppDatabase;
 
     fn into_robject(self, db: &Self::Ctx) -> Result<Self::R, Error> {
-        let res = _exact_search::<Self::R>(db, TypeFacet::Bookmark, &self.url, 10, 0)?
-            .first()
-            .cloned();
-        if let Some(res) = res {
+        let key = bookmark_key(&self.url, self.canonical.as_deref(), &tracking_params(db)?);
+        if let Some(res) = find_bookmark(db, &key)? {
             return Ok(res);
         }
 
         let time = db.now_time()?;
         let bk_id = db.new_id();
-        let mut searchable = vec![Indexed {
-            field: Fields::Text,
-            data: self.url.clone().into(),
-        }];
+        let mut searchable = vec![
+            Indexed {
+                field: Fields::Text,
+                data: self.url.clone().into(),
+            },
+            Indexed {
+                field: Fields::Key,
+                data: key.into(),
+            },
+        ];
         let _ = self.source.map(|s| {
             searchable.push(Indexed {
                 field: Fields::SourceId,
@@ -337,12 +425,21 @@ impl AutoDbAble for Image {}
 impl AutoDbAble for Bookmark {}
 impl AutoDbAble for BookmarkSource {}
 impl AutoDbAble for Tag {}
+impl AutoDbAble for SavedSearch {}
+impl AutoDbAble for Group {}
 impl AutoDbAble for serde_json::Map<String, serde_json::Value> {}
 impl AutoDbAble for tantivy::schema::Value {}
 
 // MAYBE: split this into 2 traits. one to add, another to get
 impl<T: DbAble> DbAble for Meta<T, Facet> {
     fn add(self, db: &AppDatabase, doc: &mut Document) -> Result<(), Error> {
+        // temp and internal objects are never synced
+        if !matches!(
+            TypeFacet::try_from(self.facet.to_path_string()),
+            Ok(TypeFacet::Temp(_) | TypeFacet::Internal(_))
+        ) {
+            db.sync_log.updated(self.id);
+        }
         doc.add_u64(db.get_field(Fields::Id), self.id as _);
         doc.add_facet(db.get_field(Fields::Type), self.facet);
         doc.add_u64(db.get_field(Fields::Ctime), self.ctime as _);
@@ -431,10 +528,7 @@ impl<T: DbAble> DbAble for Taggable<T> {
 }
 
 pub fn add_or_search_tag(db: &AppDatabase, tag: String) -> Result<Meta<Tag, TypeFacet>, Error> {
-    let t = _exact_search::<Meta<Tag, TypeFacet>>(db, TypeFacet::Tag, &tag, 10, 0)?
-        .first()
-        .cloned();
-    if let Some(t) = t {
+    if let Some(t) = search_tag_by_name(db, &tag)? {
         return Ok(t);
     }
 
@@ -444,13 +538,31 @@ pub fn add_or_search_tag(db: &AppDatabase, tag: String) -> Result<Meta<Tag, Type
     let t = Meta {
         id,
         facet: TypeFacet::Tag,
-        data: Tag::Main { name: tag },
+        data: Tag::Main {
+            name: tag,
+            description: None,
+            color: None,
+            icon: None,
+            cover: None,
+        },
+        ctime: time,
+        last_update: time,
+        last_interaction: time,
+    };
+    // indexed like tags saved from the frontend, so that the next lookup finds it
+    let entry = Meta {
+        id,
+        facet: TypeFacet::Tag,
+        data: SearchableEntry {
+            searchable: tag_searchables(&t.data),
+            data: t.data.clone(),
+        },
         ctime: time,
         last_update: time,
         last_interaction: time,
     };
     let mut d = Document::new();
-    t.clone().add(db, &mut d)?;
+    entry.add(db, &mut d)?;
     // TODO: OOF: BAD: very bad. don't lock writer here
     let writer = db.index_writer.write().infer_err()?;
     writer.add_document(d).infer_err()?;
@@ -458,6 +570,45 @@ pub fn add_or_search_tag(db: &AppDatabase, tag: String) -> Result<Meta<Tag, Type
     Ok(t)
 }
 
+// the tokens of the name only narrow down the tags, the stored name has to match exactly.
+// tags created by this are not visible before a commit
+pub fn search_tag_by_name(
+    db: &AppDatabase,
+    name: &str,
+) -> Result<Option<Meta<Tag, TypeFacet>>, Error> {
+    let searcher = db.get_searcher();
+    let term_query =
+        |term| Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>;
+    let mut q = vec![(
+        Occur::Must,
+        term_query(Term::from_facet(
+            db.get_field(Fields::Type),
+            &TypeFacet::Tag.facet(),
+        )),
+    )];
+    // names without any tokens are compared against all tags
+    for t in db.tokenize(name) {
+        q.push((
+            Occur::Must,
+            term_query(Term::from_field_text(db.get_field(Fields::Text), &t)),
+        ));
+    }
+    for address in searcher
+        .search(&BooleanQuery::new(q), &DocSetCollector)
+        .infer_err()?
+    {
+        let mut doc = searcher.doc(address).infer_err()?;
+        let t: Meta<Tag, TypeFacet> = DbAble::take(db, &mut doc)?;
+        let n = match &t.data {
+            Tag::Main { name, .. } | Tag::Alias { name, .. } => name,
+        };
+        if n == name {
+            return Ok(Some(t));
+        }
+    }
+    Ok(None)
+}
+
 impl<T: DbAble> DbAble for Tagged<T> {
     fn add(self, db: &AppDatabase, doc: &mut Document) -> Result<(), Error> {
         for tag in self.tags {
@@ -478,7 +629,7 @@ impl<T: DbAble> DbAble for Tagged<T> {
         let tags = _get_tags_from_ids::<Tag>(tags, db)?
             .into_iter()
             .map(|t| match t {
-                Tag::Main { name } => name,
+                Tag::Main { name, .. } => name,
                 Tag::Alias { name, .. } => name,
             })
             .collect();
@@ -535,6 +686,24 @@ impl<T: DbAble> DbAble for SearchableEntry<T> {
                     field: Fields::Text,
                     data: v,
                 })
+                .chain(
+                    doc.get_all(db.get_field(Fields::Body))
+                        .filter_map(|e| e.as_text().map(String::from))
+                        .map(Value::from)
+                        .map(|v| Indexed {
+                            field: Fields::Body,
+                            data: v,
+                        }),
+                )
+                .chain(
+                    doc.get_all(db.get_field(Fields::Key))
+                        .filter_map(|e| e.as_text().map(String::from))
+                        .map(Value::from)
+                        .map(|v| Indexed {
+                            field: Fields::Key,
+                            data: v,
+                        }),
+                )
                 .chain(
                     doc.get_all(db.get_field(Fields::SourceId))
                         .filter_map(|e| e.as_u64())
@@ -544,6 +713,15 @@ impl<T: DbAble> DbAble for SearchableEntry<T> {
                             data: v,
                         }),
                 )
+                .chain(
+                    doc.get_all(db.get_field(Fields::Size))
+                        .filter_map(|e| e.as_u64())
+                        .map(Value::from)
+                        .map(|v| Indexed {
+                            field: Fields::Size,
+                            data: v,
+                        }),
+                )
                 .collect::<Vec<_>>(),
         };
         Ok(s)
@@ -564,14 +742,24 @@ impl DbAble for Indexed {
                 doc.add_u64(db.get_field(Fields::SourceId), n.as_u64().unwrap())
             }
             (Fields::Text, serde_json::Value::String(s)) => {
+                doc.add_text(db.get_field(Fields::Prefix), &s);
                 doc.add_text(db.get_field(Fields::Text), s);
             }
+            (Fields::Body, serde_json::Value::String(s)) => {
+                doc.add_text(db.get_field(Fields::Body), s);
+            }
+            (Fields::Key, serde_json::Value::String(s)) => {
+                doc.add_text(db.get_field(Fields::Key), s);
+            }
             (Fields::Tag, serde_json::Value::Number(t_id)) => {
                 doc.add_u64(
                     db.get_field(Fields::Tag),
                     t_id.as_u64().bad_err("bad tag id")?,
                 );
             }
+            (Fields::Size, serde_json::Value::Number(n)) => {
+                doc.add_u64(db.get_field(Fields::Size), n.as_u64().bad_err("bad size")?);
+            }
             // (Fields::Tag, serde_json::Value::String(tag)) => {}
             r => {
                 dbg!(r);
@@ -629,39 +817,15 @@ where
         (
             Occur::Should,
             Box::new(BooleanQuery::new(
-                query
-                    // TODO: replace this with tantivy's query parser
-                    // TODO: add functionality to search links (needs a non default token parser i think)
-                    .split_whitespace()
-                    .flat_map(|t| t.split('_')) // for some reason - '_' and '-' in queries tank query results
-                    .flat_map(|t| t.split('-'))
-                    .filter(|t| !t.is_empty())
-                    .flat_map(|t| {
-                        [
-                            (
-                                Occur::Should,
-                                Box::new(FuzzyTermQuery::new(
-                                    Term::from_field_text(db.get_field(Fields::Text), t),
-                                    2,    // ?
-                                    true, // what??
-                                )) as _,
-                            ),
-                            (
-                                Occur::Should,
-                                Box::new(BoostQuery::new(
-                                    Box::new(TermQuery::new(
-                                        Term::from_field_text(db.get_field(Fields::Text), t),
-                                        IndexRecordOption::Basic,
-                                    )) as _,
-                                    1.0,
-                                )) as _,
-                            ),
-                        ]
-                        .into_iter()
-                    })
-                    .collect(),
+                // TODO: replace this with tantivy's query parser
+                // TODO: add functionality to search links (needs a non default token parser i think)
+                text_term_queries(db, query),
             )),
         ),
+        (
+            Occur::Should,
+            Box::new(BoostQuery::new(Box::new(body_query(db, query)), 0.3)),
+        ),
     ]);
     searcher
         .search(
@@ -704,11 +868,18 @@ where
     }
 
     let w = db.index_writer.read().infer_err()?;
-    w.delete_term(term.clone());
+    delete_object(db, &w, id as _, db.now_time()?);
 
     Ok(deleted.pop().unwrap())
 }
 
+// every object deleted by the user has to go through this, so that the deletion is synced.
+// the writer still has to be committed
+pub fn delete_object(db: &AppDatabase, writer: &IndexWriter, id: Id, time: u64) {
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    db.sync_log.deleted(id, time);
+}
+
 pub fn _exact_search<T>(
     db: &AppDatabase,
     ob_type: TypeFacet,
@@ -755,6 +926,7 @@ pub fn tagged_search<T, TScore, TScoreSegmentTweaker, TScoreTweaker>(
     db: &AppDatabase,
     ob_type: TypeFacet,
     query: impl AsRef<str>,
+    filters: &SearchFilters,
     limit: usize,
     offset: usize,
     search_tweaker: TScoreTweaker,
@@ -765,6 +937,121 @@ where
     TScoreSegmentTweaker: ScoreSegmentTweaker<TScore> + 'static,
     TScoreTweaker: ScoreTweaker<TScore, Child = TScoreSegmentTweaker> + Send + Sync,
 {
+    let q = tagged_search_query(db, ob_type, query, filters)?;
+    query_search(db, &q, limit, offset, search_tweaker)
+}
+
+pub fn query_search<T, TScore, TScoreSegmentTweaker, TScoreTweaker>(
+    db: &AppDatabase,
+    q: &BooleanQuery,
+    limit: usize,
+    offset: usize,
+    search_tweaker: TScoreTweaker,
+) -> Result<Vec<T>, Error>
+where
+    T: DbAble + Debug,
+    TScore: 'static + Send + Sync + Clone + PartialOrd + Debug,
+    TScoreSegmentTweaker: ScoreSegmentTweaker<TScore> + 'static,
+    TScoreTweaker: ScoreTweaker<TScore, Child = TScoreSegmentTweaker> + Send + Sync,
+{
+    let searcher = db.get_searcher();
+    searcher
+        .search(
+            q,
+            &TopDocs::with_limit(limit)
+                .and_offset(offset)
+                .tweak_score(search_tweaker),
+        )
+        .infer_err()?
+        .into_iter()
+        .map(move |(_score, address)| {
+            let mut doc = searcher.doc(address).infer_err()?;
+            DbAble::take(db, &mut doc).look(|e| dbg!((_score, e)))
+        })
+        .collect()
+}
+
+pub fn sorted_search<T>(
+    db: &AppDatabase,
+    ob_type: TypeFacet,
+    query: impl AsRef<str>,
+    filters: &SearchFilters,
+    sort: SearchSort,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<T>, Error>
+where
+    T: DbAble + Debug,
+{
+    let q = tagged_search_query(db, ob_type, query, filters)?;
+    sorted_query_search(db, &q, sort, limit, offset)
+}
+
+pub fn sorted_query_search<T>(
+    db: &AppDatabase,
+    q: &BooleanQuery,
+    sort: SearchSort,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<T>, Error>
+where
+    T: DbAble + Debug,
+{
+    match sort {
+        SearchSort::Relevance => {
+            query_search(db, q, limit, offset, ObjectSearchScoreTweaker::new(db)?)
+        }
+        SearchSort::Newest => query_search(
+            db,
+            q,
+            limit,
+            offset,
+            FieldSortScoreTweaker::new(db, Fields::Ctime, false),
+        ),
+        SearchSort::Oldest => query_search(
+            db,
+            q,
+            limit,
+            offset,
+            FieldSortScoreTweaker::new(db, Fields::Ctime, true),
+        ),
+        SearchSort::RecentlyViewed => query_search(
+            db,
+            q,
+            limit,
+            offset,
+            FieldSortScoreTweaker::new(db, Fields::LastInteraction, false),
+        ),
+    }
+}
+
+// every object of the type, ranked by how well it matches the query
+pub fn tagged_search_query(
+    db: &AppDatabase,
+    ob_type: TypeFacet,
+    query: impl AsRef<str>,
+    filters: &SearchFilters,
+) -> Result<BooleanQuery, Error> {
+    tagged_query(db, ob_type, query.as_ref(), filters, false)
+}
+
+// only the objects that match the query, for counting and exporting them
+pub fn tagged_match_query(
+    db: &AppDatabase,
+    ob_type: TypeFacet,
+    query: impl AsRef<str>,
+    filters: &SearchFilters,
+) -> Result<BooleanQuery, Error> {
+    tagged_query(db, ob_type, query.as_ref(), filters, true)
+}
+
+fn tagged_query(
+    db: &AppDatabase,
+    ob_type: TypeFacet,
+    query: &str,
+    filters: &SearchFilters,
+    only_matches: bool,
+) -> Result<BooleanQuery, Error> {
     // TODO: look into using QueryParser, or maybe somehow including regex queries and stuff
     // TODO: is query is empty, return results by recently added or recently viewed or a mixture of this stuff
     //       - 2 fast values, 1 each for when added timestamp and for last viewed timestamp
@@ -777,29 +1064,44 @@ where
     //   OR title:FuzzyTermQuery(split at whitespace map) -> priority 3
     // )
     let searcher = db.get_searcher();
-    let query = query.as_ref();
 
     let obj_type_query = Box::new(TermQuery::new(
         Term::from_facet(db.get_field(Fields::Type), &ob_type.facet()),
         IndexRecordOption::Basic,
     ));
 
-    let phrase_query_terms = query
-        .split_whitespace()
-        .flat_map(|t| t.split('_')) // for some reason - '_' and '-' in queries tank query results
-        .flat_map(|t| t.split('-'))
-        .filter(|t| !t.is_empty())
+    let tokens = db.tokenize(query);
+    let mut phrase_query_terms = tokens
+        .iter()
         .map(|t| Term::from_field_text(db.get_field(Fields::Text), t))
         .collect::<Vec<_>>();
     let title_query = if phrase_query_terms.len() < 2 {
         // PhraseQuery does not support less than 2 terms
         Box::new(TermQuery::new(
-            Term::from_field_text(db.get_field(Fields::Text), query),
+            phrase_query_terms
+                .pop()
+                .unwrap_or_else(|| Term::from_field_text(db.get_field(Fields::Text), query)),
             IndexRecordOption::Basic,
         )) as _
     } else {
         Box::new(PhraseQuery::new(phrase_query_terms)) as _
     };
+    // the last word might not be typed out completely yet
+    let prefix_query = Box::new(BooleanQuery::new(
+        tokens
+            .last()
+            .map(|t| {
+                (
+                    Occur::Should,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(db.get_field(Fields::Prefix), t),
+                        IndexRecordOption::Basic,
+                    )) as _,
+                )
+            })
+            .into_iter()
+            .collect(),
+    ));
 
     // - search tags
     // - replace all alias tags by main
@@ -819,37 +1121,7 @@ where
         ),
         (
             Occur::Must,
-            Box::new(BooleanQuery::new(
-                query
-                    .split_whitespace()
-                    .flat_map(|t| t.split('_')) // for some reason - '_' and '-' in queries tank query results
-                    .flat_map(|t| t.split('-'))
-                    .filter(|t| !t.is_empty())
-                    .flat_map(|t| {
-                        [
-                            (
-                                Occur::Should,
-                                Box::new(FuzzyTermQuery::new(
-                                    Term::from_field_text(db.get_field(Fields::Text), t),
-                                    2,    // ?
-                                    true, // what??
-                                )) as _,
-                            ),
-                            (
-                                Occur::Should,
-                                Box::new(BoostQuery::new(
-                                    Box::new(TermQuery::new(
-                                        Term::from_field_text(db.get_field(Fields::Text), t),
-                                        IndexRecordOption::Basic,
-                                    )) as _,
-                                    1.0,
-                                )) as _,
-                            ),
-                        ]
-                        .into_iter()
-                    })
-                    .collect(),
-            )),
+            Box::new(BooleanQuery::new(text_term_queries(db, query))),
         ),
     ]);
     let tags = searcher
@@ -888,34 +1160,8 @@ where
 
     let title_fuzzy_query = Box::new(BooleanQuery::new(
         // TODO: implement these as methods of Fields and ObjectType
-        query
-            .split_whitespace()
-            .flat_map(|t| t.split('_')) // for some reason - '_' and '-' in queries tank query results
-            .flat_map(|t| t.split('-'))
-            .flat_map(|t| {
-                [
-                    (
-                        Occur::Should, // TODO: should this be Must instead?
-                        Box::new(FuzzyTermQuery::new(
-                            Term::from_field_text(db.get_field(Fields::Text), t),
-                            2,    // ?
-                            true, // what??
-                        )) as _,
-                    ),
-                    (
-                        Occur::Should,
-                        Box::new(BoostQuery::new(
-                            Box::new(TermQuery::new(
-                                Term::from_field_text(db.get_field(Fields::Text), t),
-                                IndexRecordOption::Basic,
-                            )) as _,
-                            1.0,
-                        )) as _,
-                    ),
-                ]
-                .into_iter()
-            })
-            .collect(),
+        // TODO: should these be Must instead?
+        text_term_queries(db, query),
     ));
 
     let search_query = Box::new(BooleanQuery::new(vec![
@@ -923,14 +1169,191 @@ where
         (Occur::Should, title_query),
         (Occur::Should, Box::new(BoostQuery::new(tag_query, 2.0))),
         (Occur::Should, title_fuzzy_query),
+        (Occur::Should, Box::new(BoostQuery::new(prefix_query, 0.5))),
+        (
+            Occur::Should,
+            Box::new(BoostQuery::new(Box::new(body_query(db, query)), 0.3)),
+        ),
     ]));
 
+    let mut q: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, obj_type_query)];
+    // a query without any words has nothing to match
+    if only_matches && !tokens.is_empty() {
+        q.push((Occur::Must, search_query));
+    } else {
+        q.push((Occur::Should, Box::new(AllQuery)));
+        q.push((Occur::Should, search_query));
+    }
+    q.extend(search_filter_queries(db, filters));
+    Ok(BooleanQuery::new(q))
+}
+
+// exact match for every token in the query. pages are long, so no fuzzy matching here
+pub fn body_query(db: &AppDatabase, query: &str) -> BooleanQuery {
+    BooleanQuery::new(
+        db.tokenize(query)
+            .into_iter()
+            .map(|t| {
+                (
+                    Occur::Should,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(db.get_field(Fields::Body), &t),
+                        IndexRecordOption::WithFreqsAndPositions,
+                    )) as _,
+                )
+            })
+            .collect(),
+    )
+}
+
+// fuzzy + exact match for every token in the query
+pub fn text_term_queries(db: &AppDatabase, query: &str) -> Vec<(Occur, Box<dyn Query>)> {
+    db.tokenize(query)
+        .into_iter()
+        .flat_map(|t| {
+            let term = Term::from_field_text(db.get_field(Fields::Text), &t);
+            // fuzzy matching a 2 char cjk bigram matches just about everything
+            let fuzzy = (!t.chars().any(is_cjk)).then(|| {
+                (
+                    Occur::Should,
+                    Box::new(FuzzyTermQuery::new(
+                        term.clone(),
+                        2,    // ?
+                        true, // what??
+                    )) as Box<dyn Query>,
+                )
+            });
+            let exact = (
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as _,
+                    1.0,
+                )) as Box<dyn Query>,
+            );
+            fuzzy.into_iter().chain([exact])
+        })
+        .collect()
+}
+
+pub fn search_filter_queries(
+    db: &AppDatabase,
+    filters: &SearchFilters,
+) -> Vec<(Occur, Box<dyn Query>)> {
+    let tags = filters.tags.iter().map(|&t| {
+        (
+            Occur::Must,
+            Box::new(TermQuery::new(
+                Term::from_field_u64(db.get_field(Fields::Tag), t as _),
+                IndexRecordOption::Basic,
+            )) as _,
+        )
+    });
+    let ranges = [
+        (Fields::Ctime, filters.created),
+        (Fields::Mtime, filters.modified),
+        (Fields::LastInteraction, filters.last_interaction),
+        (Fields::Size, filters.size),
+    ]
+    .into_iter()
+    .filter(|(_, r)| r.min.is_some() || r.max.is_some())
+    .map(|(f, r)| {
+        (
+            Occur::Must,
+            Box::new(RangeQuery::new_u64_bounds(
+                db.get_field(f),
+                r.min.map(Bound::Included).unwrap_or(Bound::Unbounded),
+                r.max.map(Bound::Excluded).unwrap_or(Bound::Unbounded),
+            )) as _,
+        )
+    });
+    tags.chain(ranges).collect()
+}
+
+// facet:TermQuery(same facet as the object) AND NOT id:TermQuery(the object itself) AND (
+//   tag(s):TermQuery(tags of the object)
+//   OR text:MoreLikeThisQuery(text of the object)
+//   OR source:TermQuery(source of the object)
+// )
+pub fn related_search<T, TScore, TScoreSegmentTweaker, TScoreTweaker>(
+    db: &AppDatabase,
+    id: Id,
+    limit: usize,
+    offset: usize,
+    search_tweaker: TScoreTweaker,
+) -> Result<Vec<T>, Error>
+where
+    T: DbAble + Debug,
+    TScore: 'static + Send + Sync + Clone + PartialOrd + Debug,
+    TScoreSegmentTweaker: ScoreSegmentTweaker<TScore> + 'static,
+    TScoreTweaker: ScoreTweaker<TScore, Child = TScoreSegmentTweaker> + Send + Sync,
+{
+    let searcher = db.get_searcher();
+    let doc = db.get_doc(id)?;
+
+    let facet = doc
+        .get_first(db.get_field(Fields::Type))
+        .and_then(|f| f.as_facet().map(|f| f.to_owned()))
+        .bad_err("bad facet")?;
+    let obj_type_query = Box::new(TermQuery::new(
+        Term::from_facet(db.get_field(Fields::Type), &facet),
+        IndexRecordOption::Basic,
+    ));
+    let self_query = Box::new(TermQuery::new(
+        Term::from_field_u64(db.get_field(Fields::Id), id as _),
+        IndexRecordOption::Basic,
+    ));
+
+    let mut related: Vec<(Occur, Box<dyn Query>)> = vec![];
+
+    // each shared tag is worth more than a few shared words
+    doc.get_all(db.get_field(Fields::Tag))
+        .filter_map(|t| t.as_u64())
+        .for_each(|t| {
+            related.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(
+                        Term::from_field_u64(db.get_field(Fields::Tag), t),
+                        IndexRecordOption::Basic,
+                    )),
+                    2.0,
+                )),
+            ));
+        });
+
+    let text = doc
+        .get_all(db.get_field(Fields::Text))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !text.is_empty() {
+        // the defaults are tuned for large corpora. a personal collection rarely
+        // has terms that appear 5 times in 5 different documents
+        let mlt = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(1)
+            .with_min_term_frequency(1)
+            .with_min_word_length(2)
+            .with_document_fields(vec![(db.get_field(Fields::Text), text)]);
+        related.push((Occur::Should, Box::new(mlt)));
+    }
+
+    doc.get_all(db.get_field(Fields::SourceId))
+        .filter_map(|s| s.as_u64())
+        .for_each(|s| {
+            related.push((
+                Occur::Should,
+                Box::new(TermQuery::new(
+                    Term::from_field_u64(db.get_field(Fields::SourceId), s),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        });
+
     searcher
         .search(
             &BooleanQuery::new(vec![
                 (Occur::Must, obj_type_query),
-                (Occur::Should, Box::new(AllQuery)),
-                (Occur::Should, search_query),
+                (Occur::MustNot, self_query),
+                (Occur::Must, Box::new(BooleanQuery::new(related))),
             ]),
             &TopDocs::with_limit(limit)
                 .and_offset(offset)
@@ -949,6 +1372,8 @@ pub struct TagSearchScoreTweaker {
     pub now: u64,
     pub last_interaction: Field,
     pub id_field: Field,
+    // number of objects tagged with each tag
+    pub usage: Arc<HashMap<u64, u64>>,
 }
 
 impl TagSearchScoreTweaker {
@@ -960,26 +1385,34 @@ impl TagSearchScoreTweaker {
             now,
             last_interaction,
             id_field,
+            usage: Default::default(),
         };
         Ok(s)
     }
+
+    pub fn with_usage(mut self, usage: Arc<HashMap<u64, u64>>) -> Self {
+        self.usage = usage;
+        self
+    }
 }
 
 pub struct TagSearchScoreSegmentTweaker {
     pub last_interaction: Arc<dyn Column<u64>>,
     pub id_reader: Arc<dyn Column<u64>>,
+    pub usage: Arc<HashMap<u64, u64>>,
     pub now: u64,
 }
 
-type TagSearchTweakedScore = (tantivy::Score, u64, u64);
+type TagSearchTweakedScore = (tantivy::Score, u64, u64, u64);
 
 impl ScoreSegmentTweaker<TagSearchTweakedScore> for TagSearchScoreSegmentTweaker {
     fn score(&mut self, doc: tantivy::DocId, score: tantivy::Score) -> TagSearchTweakedScore {
         let last_interaction = self.last_interaction.get_val(doc);
         let id = self.id_reader.get_val(doc);
+        let usage = self.usage.get(&id).copied().unwrap_or(0);
 
         // PartialOrd on tuples: https://stackoverflow.com/a/61323034
-        (score, last_interaction, id)
+        (score, usage, last_interaction, id)
     }
 }
 impl ScoreTweaker<TagSearchTweakedScore> for TagSearchScoreTweaker {
@@ -992,6 +1425,7 @@ impl ScoreTweaker<TagSearchTweakedScore> for TagSearchScoreTweaker {
         let tw = TagSearchScoreSegmentTweaker {
             last_interaction,
             id_reader,
+            usage: self.usage.clone(),
             now: self.now,
         };
         Ok(tw)
@@ -1062,6 +1496,57 @@ impl ScoreTweaker<ObjectSearchTweakedScore> for ObjectSearchScoreTweaker {
     }
 }
 
+// orders results by a fast field first. relevance only breaks ties
+pub struct FieldSortScoreTweaker {
+    pub field: Field,
+    pub id_field: Field,
+    pub ascending: bool,
+}
+
+impl FieldSortScoreTweaker {
+    pub fn new(db: &AppDatabase, field: Fields, ascending: bool) -> Self {
+        Self {
+            field: db.get_field(field),
+            id_field: db.get_field(Fields::Id),
+            ascending,
+        }
+    }
+}
+
+pub struct FieldSortScoreSegmentTweaker {
+    pub reader: Arc<dyn Column<u64>>,
+    pub id_reader: Arc<dyn Column<u64>>,
+    pub ascending: bool,
+}
+
+type FieldSortTweakedScore = (u64, tantivy::Score, u64);
+
+impl ScoreSegmentTweaker<FieldSortTweakedScore> for FieldSortScoreSegmentTweaker {
+    fn score(&mut self, doc: tantivy::DocId, score: tantivy::Score) -> FieldSortTweakedScore {
+        let v = self.reader.get_val(doc);
+        let id = self.id_reader.get_val(doc);
+
+        // TopDocs keeps the highest scores
+        let v = if self.ascending { u64::MAX - v } else { v };
+        (v, score, id)
+    }
+}
+impl ScoreTweaker<FieldSortTweakedScore> for FieldSortScoreTweaker {
+    type Child = FieldSortScoreSegmentTweaker;
+
+    fn segment_tweaker(&self, segment_reader: &SegmentReader) -> tantivy::Result<Self::Child> {
+        let reader = segment_reader.fast_fields().u64(self.field)?;
+        let id_reader = segment_reader.fast_fields().u64(self.id_field)?;
+
+        let tw = FieldSortScoreSegmentTweaker {
+            reader,
+            id_reader,
+            ascending: self.ascending,
+        };
+        Ok(tw)
+    }
+}
+
 pub async fn init_database(app_handle: &AppHandle, conf: &AppConfig) -> Result<(), Error> {
     let handle = app_handle.app_handle();
 
@@ -1073,7 +1558,7 @@ pub async fn init_database(app_handle: &AppHandle, conf: &AppConfig) -> Result<(
             &TermQuery::new(
                 Term::from_facet(
                     db.get_field(Fields::Type),
-                    &TypeFacet::Temp("/app_data/state".into()).facet(),
+                    &TypeFacet::Internal("/app_data/state".into()).facet(),
                 ),
                 IndexRecordOption::Basic,
             ),
@@ -1099,7 +1584,7 @@ pub async fn init_database(app_handle: &AppHandle, conf: &AppConfig) -> Result<(
                 let db = handle.state::<AppDatabase>().inner();
 
                 // TODO: Temp really?
-                let facet = TypeFacet::Temp("/app_data/state".into()).facet();
+                let facet = TypeFacet::Internal("/app_data/state".into()).facet();
 
                 let mut writer = db.index_writer.write().unwrap();
                 let _opstamp =
@@ -1135,6 +1620,7 @@ pub struct AppDatabase {
     pub index_writer: RwLock<IndexWriter>, // TODO: make commits explicit (don't commit in add_object functions. commit should be called when needed explicitly)
     fields: HashMap<Fields, Field>,
     id_gen: AtomicU32,
+    pub sync_log: SyncLog,
 }
 
 // the state that persists
@@ -1153,6 +1639,8 @@ impl AppDatabase {
             std::fs::create_dir(&db_dir).infer_err()?;
         }
         let tantivy_dir = db_dir.join("tantivy");
+        recover_migration(&tantivy_dir)?;
+        apply_staged_restore(&tantivy_dir)?;
         if !tantivy_dir.exists() {
             std::fs::create_dir(&tantivy_dir).infer_err()?;
         }
@@ -1192,11 +1680,25 @@ impl AppDatabase {
         let object_type =
             schema_builder.add_facet_field(&Fields::Type, FacetOptions::default().set_stored());
         let _ = fields.insert(Fields::Type, object_type);
-        let text = schema_builder.add_text_field(&Fields::Text, STORED | TEXT);
+        let text = schema_builder.add_text_field(&Fields::Text, text_options());
         let _ = fields.insert(Fields::Text, text);
+        let prefix = schema_builder.add_text_field(&Fields::Prefix, prefix_options());
+        let _ = fields.insert(Fields::Prefix, prefix);
+        let body = schema_builder.add_text_field(&Fields::Body, text_options());
+        let _ = fields.insert(Fields::Body, body);
+        let key = schema_builder.add_text_field(&Fields::Key, STRING | STORED);
+        let _ = fields.insert(Fields::Key, key);
         let chksum = schema_builder.add_bytes_field(&Fields::Chksum, STORED);
         let _ = fields.insert(Fields::Chksum, chksum);
-        let tag = schema_builder.add_u64_field(&Fields::Tag, STORED | FAST | INDEXED);
+        // objects have many tags, so the fast field has to be multi valued. changing the
+        // options of a field migrates the index (see migrate_index)
+        let tag = schema_builder.add_u64_field(
+            &Fields::Tag,
+            NumericOptions::default()
+                .set_stored()
+                .set_indexed()
+                .set_fast(Cardinality::MultiValues),
+        );
         let _ = fields.insert(Fields::Tag, tag);
         let json = schema_builder.add_json_field(&Fields::Json, STORED);
         let _ = fields.insert(Fields::Json, json);
@@ -1207,11 +1709,25 @@ impl AppDatabase {
         let last_interaction =
             schema_builder.add_u64_field(&Fields::LastInteraction, STORED | FAST | INDEXED);
         let _ = fields.insert(Fields::LastInteraction, last_interaction);
+        let size = schema_builder.add_u64_field(&Fields::Size, STORED | FAST | INDEXED);
+        let _ = fields.insert(Fields::Size, size);
 
         let schema = schema_builder.build();
-        let dir = ManagedDirectory::wrap(Box::new(MmapDirectory::open(tantivy_dir).infer_err()?))
+        if tantivy_dir.join("meta.json").exists() {
+            let old = Index::open_in_dir(&tantivy_dir).infer_err()?;
+            if old.schema() != schema {
+                migrate_index(old, &tantivy_dir, schema.clone(), &fields)?;
+            }
+        }
+        let dir = ManagedDirectory::wrap(Box::new(MmapDirectory::open(&tantivy_dir).infer_err()?))
             .infer_err()?;
         let index = Index::open_or_create(dir, schema).infer_err()?;
+        register_tokenizers(&index);
+        // the migrated index opens, so the one from before the migration is not needed
+        let old_dir = migration_backup_dir(&tantivy_dir);
+        if old_dir.exists() {
+            std::fs::remove_dir_all(&old_dir).infer_err()?;
+        }
         let index_writer = index.writer(50_000_000).infer_err()?;
 
         let index_reader = index.reader_builder().try_into().infer_err()?;
@@ -1223,6 +1739,7 @@ impl AppDatabase {
             index,
             fields,
             id_gen: 0.into(),
+            sync_log: SyncLog::default(),
         })
     }
 
@@ -1240,6 +1757,25 @@ impl AppDatabase {
         *self.fields.get(&f).unwrap()
     }
 
+    pub fn schema(&self) -> Schema {
+        self.index.schema()
+    }
+
+    // tokens the way Fields::Text is indexed
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        if let Some(analyzer) = self.index.tokenizers().get(TEXT_TOKENIZER) {
+            let _ = analyzer
+                .token_stream(text)
+                .process(&mut |t| tokens.push(t.text.clone()));
+        }
+        tokens
+    }
+
+    pub fn load_metas(&self) -> Result<IndexMeta, Error> {
+        self.index.load_metas().infer_err()
+    }
+
     pub fn get_searcher(&self) -> Searcher {
         Searcher(self.index_reader.searcher())
     }
@@ -1265,6 +1801,12 @@ impl AppDatabase {
         Ok(doc)
     }
 
+    // fills the fields that are indexed but not stored, for documents rebuilt from their
+    // stored fields
+    pub fn derive_fields(&self, doc: &mut Document) {
+        derive_fields(&self.fields, &self.schema(), doc);
+    }
+
     pub fn new_id(&self) -> Id {
         self.id_gen
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
@@ -1279,6 +1821,118 @@ impl AppDatabase {
     }
 }
 
+// tantivy can't open an index with a different schema, so all stored documents are copied
+// into a new index with the current schema and the directories are swapped
+fn migrate_index(
+    old: Index,
+    dir: &std::path::Path,
+    schema: Schema,
+    fields: &HashMap<Fields, Field>,
+) -> Result<(), Error> {
+    let new_dir = dir.with_file_name("tantivy_migration");
+    if new_dir.exists() {
+        std::fs::remove_dir_all(&new_dir).infer_err()?;
+    }
+    std::fs::create_dir(&new_dir).infer_err()?;
+
+    let old_schema = old.schema();
+    let searcher = old.reader().infer_err()?.searcher();
+    let index = Index::create_in_dir(&new_dir, schema.clone()).infer_err()?;
+    register_tokenizers(&index);
+    let mut writer: IndexWriter = index.writer(50_000_000).infer_err()?;
+    for (ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in segment_reader.doc_ids_alive() {
+            let old_doc = searcher
+                .doc(DocAddress::new(ord as _, doc_id))
+                .infer_err()?;
+            let mut doc = Document::new();
+            for fv in old_doc.field_values() {
+                if let Some(f) = schema.get_field(old_schema.get_field_name(fv.field())) {
+                    doc.add_field_value(f, fv.value().clone());
+                }
+            }
+            derive_fields(fields, &old_schema, &mut doc);
+            let _opstamp = writer.add_document(doc).infer_err()?;
+        }
+    }
+    let _opstamp = writer.commit().infer_err()?;
+    writer.wait_merging_threads().infer_err()?;
+    drop(searcher);
+    drop(old);
+
+    // the old index is kept until the new one opens. see recover_migration
+    let old_dir = migration_backup_dir(dir);
+    if old_dir.exists() {
+        std::fs::remove_dir_all(&old_dir).infer_err()?;
+    }
+    std::fs::rename(dir, &old_dir).infer_err()?;
+    std::fs::rename(&new_dir, dir).infer_err()?;
+    Ok(())
+}
+
+fn migration_backup_dir(dir: &std::path::Path) -> std::path::PathBuf {
+    dir.with_file_name("tantivy_old")
+}
+
+// puts the index from before a migration back if the app stopped in the middle of swapping
+// the directories, or if the migrated index does not open
+fn recover_migration(dir: &std::path::Path) -> Result<(), Error> {
+    let old_dir = migration_backup_dir(dir);
+    if !old_dir.exists() || Index::open_in_dir(dir).is_ok() {
+        return Ok(());
+    }
+    if dir.exists() {
+        std::fs::remove_dir_all(dir).infer_err()?;
+    }
+    std::fs::rename(&old_dir, dir).infer_err()?;
+    Ok(())
+}
+
+// fills the fields that the old schema did not have (or did not store) from the stored data
+fn derive_fields(fields: &HashMap<Fields, Field>, old_schema: &Schema, doc: &mut Document) {
+    let text = doc
+        .get_all(fields[&Fields::Text])
+        .filter_map(|t| t.as_text())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    for t in text {
+        doc.add_text(fields[&Fields::Prefix], t);
+    }
+
+    let is_image = doc
+        .get_first(fields[&Fields::Type])
+        .and_then(|f| f.as_facet())
+        .map(|f| *f == TypeFacet::Image.facet())
+        .unwrap_or(false);
+    let is_bookmark = doc
+        .get_first(fields[&Fields::Type])
+        .and_then(|f| f.as_facet())
+        .map(|f| *f == TypeFacet::Bookmark.facet())
+        .unwrap_or(false);
+    if is_bookmark && old_schema.get_field(&Fields::Key).is_none() {
+        let bk = doc
+            .get_first(fields[&Fields::Json])
+            .and_then(|j| j.as_json())
+            .and_then(|j| serde_json::from_value::<Bookmark>(Value::Object(j.clone())).ok());
+        if let Some(bk) = bk {
+            // there can't be a saved list of tracking params before there are keys
+            let key = bookmark_key(&bk.url, bk.canonical.as_deref(), &default_tracking_params());
+            doc.add_text(fields[&Fields::Key], key);
+        }
+    }
+
+    if is_image && old_schema.get_field(&Fields::Size).is_none() {
+        let size = doc
+            .get_first(fields[&Fields::Json])
+            .and_then(|j| j.as_json())
+            .and_then(|j| j.get("size"))
+            .and_then(|s| s.as_u64());
+        if let Some(size) = size {
+            doc.add_u64(fields[&Fields::Size], size);
+        }
+    }
+}
+
 pub trait FacetFrom
 where
     Self: AsRef<str>,
@@ -1299,3 +1953,102 @@ impl Deref for Searcher {
         &self.0
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn test_config(dir: &std::path::Path) -> AppConfig {
+        AppConfig {
+            app_data_dir: dir.join("data"),
+            app_config_dir: dir.join("config"),
+            app_cache_dir: dir.join("cache"),
+            app_log_dir: dir.join("log"),
+            home_dir: dir.to_path_buf(),
+        }
+    }
+
+    // the directory has to outlive the database
+    pub async fn test_db() -> (tempfile::TempDir, AppDatabase) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        std::fs::create_dir_all(&config.app_data_dir).unwrap();
+        let db = AppDatabase::new(&config).await.unwrap();
+        (dir, db)
+    }
+
+    pub fn commit(db: &AppDatabase) {
+        let _opstamp = db.index_writer.write().unwrap().commit().unwrap();
+        db.index_reader.reload().unwrap();
+    }
+
+    // a bookmark with nothing but some searchable text and tags
+    pub fn test_object(
+        db: &AppDatabase,
+        text: &str,
+        tags: Vec<Id>,
+        time: u64,
+    ) -> RObject<serde_json::Map<String, Value>> {
+        Meta {
+            id: db.new_id(),
+            facet: TypeFacet::Bookmark,
+            data: SearchableEntry {
+                searchable: vec![Indexed {
+                    field: Fields::Text,
+                    data: text.into(),
+                }],
+                data: Taggable {
+                    data: serde_json::Map::new(),
+                    tags,
+                },
+            },
+            ctime: time,
+            last_update: time,
+            last_interaction: time,
+        }
+    }
+
+    pub fn object_doc(db: &AppDatabase, obj: impl DbAble) -> Document {
+        let mut doc = Document::new();
+        obj.add(db, &mut doc).unwrap();
+        doc
+    }
+
+    // without committing
+    pub fn add_object(db: &AppDatabase, obj: impl DbAble) {
+        let doc = object_doc(db, obj);
+        let _opstamp = db.index_writer.read().unwrap().add_document(doc).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tags_are_found_by_exact_name() {
+        let (_dir, db) = test_db().await;
+        let names = ["rust", "rust lang", "say \"hi\"", "++"];
+        let ids = names
+            .iter()
+            .map(|n| add_or_search_tag(&db, n.to_string()).unwrap().id)
+            .collect::<Vec<_>>();
+        commit(&db);
+
+        for (n, id) in names.iter().zip(ids.iter()) {
+            assert_eq!(add_or_search_tag(&db, n.to_string()).unwrap().id, *id);
+        }
+        assert!(search_tag_by_name(&db, "lang").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn interrupted_migrations_are_undone() {
+        let (dir, db) = test_db().await;
+        let id = add_or_search_tag(&db, "rust".into()).unwrap().id;
+        commit(&db);
+        drop(db);
+
+        // stopped between moving the old index away and moving the new one in
+        let config = test_config(dir.path());
+        let index = config.app_data_dir.join("database").join("tantivy");
+        std::fs::rename(&index, migration_backup_dir(&index)).unwrap();
+        let db = AppDatabase::new(&config).await.unwrap();
+        assert!(db.get_doc(id).is_ok());
+        assert!(!migration_backup_dir(&index).exists());
+    }
+}
@@ -0,0 +1,576 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use kolekk_types::{
+    objects::{
+        Bookmark, BookmarkExportFormat, Fields, Id, Meta, SearchFilters, Tag, Taggable, Tagged,
+        TypeFacet,
+    },
+    utility::Path,
+};
+use tantivy::{
+    collector::DocSetCollector,
+    query::{BooleanQuery, Occur, TermQuery},
+    schema::IndexRecordOption,
+    DocAddress, Term,
+};
+use tauri::State;
+
+use crate::{
+    bad_error::{Error, InferBadError},
+    config::AppConfig,
+    database::{tagged_match_query, AppDatabase, DbAble, Searcher},
+    filesystem::get_path,
+    tag::_get_tags_from_ids,
+};
+
+type TaggedBookmark = Meta<Taggable<Bookmark>, TypeFacet>;
+
+// writes the bookmarks matching the query, tags and source in the tagged markdown format
+// that bookmark sources are parsed from. also writes it to path if given
+#[tauri::command]
+pub async fn export_bookmarks_markdown(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    query: String,
+    tags: Vec<Id>,
+    source: Option<Id>,
+    path: Option<Path>,
+) -> Result<String, Error> {
+    let db = db.inner();
+    let searcher = db.get_searcher();
+    let mut bookmarks = bookmark_addresses(db, &searcher, &query, tags, source)?
+        .into_iter()
+        .map(|address| {
+            let mut doc = searcher.doc(address).infer_err()?;
+            DbAble::take(db, &mut doc)
+        })
+        .collect::<Result<Vec<TaggedBookmark>, Error>>()?;
+    bookmarks.sort_by_key(|b| (b.ctime, b.id));
+    let names = tag_names(
+        db,
+        bookmarks.iter().flat_map(|b| b.data.tags.iter().copied()),
+    )?;
+    let md = bookmarks_to_markdown(&bookmarks, &names);
+    if let Some(path) = path {
+        std::fs::write(get_path(&path, config.inner()), &md).infer_err()?;
+    }
+    Ok(md)
+}
+
+// the bookmarks are written one at a time, so large libraries never have to be in memory
+// at once. returns the number of bookmarks written
+#[tauri::command]
+pub async fn export_bookmarks(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    format: BookmarkExportFormat,
+    query: String,
+    tags: Vec<Id>,
+    source: Option<Id>,
+    path: Path,
+) -> Result<usize, Error> {
+    let db = db.inner();
+    let searcher = db.get_searcher();
+    let addresses = bookmark_addresses(db, &searcher, &query, tags, source)?;
+
+    // the tags are needed up front to group the bookmarks into folders
+    let bookmark_tags = bookmark_tags(db, &searcher, &addresses)?;
+    let names = tag_names(db, bookmark_tags.iter().flatten().copied())?;
+
+    let export = Export {
+        db,
+        searcher: &searcher,
+        addresses: &addresses,
+        tags: &bookmark_tags,
+        names: &names,
+    };
+    let mut out = BufWriter::new(File::create(get_path(&path, config.inner())).infer_err()?);
+    match format {
+        BookmarkExportFormat::Html => export.html(&mut out)?,
+        BookmarkExportFormat::Json => export.json(&mut out)?,
+        BookmarkExportFormat::Csv => export.csv(&mut out)?,
+    }
+    out.flush().infer_err()?;
+    Ok(addresses.len())
+}
+
+// read from the fast field, so that the bookmarks don't have to be loaded twice
+fn bookmark_tags(
+    db: &AppDatabase,
+    searcher: &Searcher,
+    addresses: &[DocAddress],
+) -> Result<Vec<Vec<Id>>, Error> {
+    let readers = searcher
+        .segment_readers()
+        .iter()
+        .map(|r| r.fast_fields().u64s(db.get_field(Fields::Tag)))
+        .collect::<Result<Vec<_>, _>>()
+        .infer_err()?;
+    let mut vals = vec![];
+    let tags = addresses
+        .iter()
+        .map(|a| {
+            readers[a.segment_ord as usize].get_vals(a.doc_id, &mut vals);
+            vals.iter().map(|&t| t as Id).collect::<Vec<_>>()
+        })
+        .collect();
+    Ok(tags)
+}
+
+fn bookmark_addresses(
+    db: &AppDatabase,
+    searcher: &Searcher,
+    query: &str,
+    tags: Vec<Id>,
+    source: Option<Id>,
+) -> Result<Vec<DocAddress>, Error> {
+    let filters = SearchFilters {
+        tags,
+        ..Default::default()
+    };
+    let mut q = vec![(
+        Occur::Must,
+        Box::new(tagged_match_query(
+            db,
+            TypeFacet::Bookmark,
+            query,
+            &filters,
+        )?) as _,
+    )];
+    if let Some(s) = source {
+        q.push((
+            Occur::Must,
+            Box::new(TermQuery::new(
+                Term::from_field_u64(db.get_field(Fields::SourceId), s as _),
+                IndexRecordOption::Basic,
+            )) as _,
+        ));
+    }
+    let mut addresses = searcher
+        .search(&BooleanQuery::new(q), &DocSetCollector)
+        .infer_err()?
+        .into_iter()
+        .collect::<Vec<_>>();
+    // roughly the order they were added in
+    addresses.sort_unstable();
+    Ok(addresses)
+}
+
+fn tag_names(
+    db: &AppDatabase,
+    ids: impl IntoIterator<Item = Id>,
+) -> Result<HashMap<Id, String>, Error> {
+    let mut ids = ids.into_iter().collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    let tags: Vec<Meta<Tag, TypeFacet>> = _get_tags_from_ids(ids, db)?;
+    let names = tags
+        .into_iter()
+        .map(|t| match t.data {
+            Tag::Main { name, .. } | Tag::Alias { name, .. } => (t.id, name),
+        })
+        .collect();
+    Ok(names)
+}
+
+struct Export<'a> {
+    db: &'a AppDatabase,
+    searcher: &'a Searcher,
+    addresses: &'a [DocAddress],
+    // tags of the bookmark at the same index in addresses
+    tags: &'a [Vec<Id>],
+    names: &'a HashMap<Id, String>,
+}
+
+impl Export<'_> {
+    fn load(&self, i: usize) -> Result<TaggedBookmark, Error> {
+        let mut doc = self.searcher.doc(self.addresses[i]).infer_err()?;
+        DbAble::take(self.db, &mut doc)
+    }
+
+    fn names_of(&self, i: usize) -> Vec<&str> {
+        self.tags[i]
+            .iter()
+            .filter_map(|t| self.names.get(t))
+            .map(String::as_str)
+            .collect()
+    }
+
+    // every bookmark goes into the folder of its most used tag. all tags go in TAGS
+    fn html(&self, out: &mut impl Write) -> Result<(), Error> {
+        let mut usage = HashMap::<Id, usize>::new();
+        for t in self.tags.iter().flatten() {
+            *usage.entry(*t).or_default() += 1;
+        }
+        let mut folders = BTreeMap::<&str, Vec<usize>>::new();
+        let mut root = vec![];
+        for (i, tags) in self.tags.iter().enumerate() {
+            let folder = tags
+                .iter()
+                .filter(|t| self.names.contains_key(*t))
+                .max_by_key(|t| (usage[*t], std::cmp::Reverse(**t)))
+                .map(|t| self.names[t].as_str());
+            match folder {
+                Some(f) => folders.entry(f).or_default().push(i),
+                None => root.push(i),
+            }
+        }
+
+        writeln!(
+            out,
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+            <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+            <TITLE>Bookmarks</TITLE>\n\
+            <H1>Bookmarks</H1>\n\
+            <DL><p>"
+        )
+        .infer_err()?;
+        for (folder, bookmarks) in folders {
+            writeln!(out, "    <DT><H3>{}</H3>\n    <DL><p>", escape_html(folder)).infer_err()?;
+            for i in bookmarks {
+                self.html_bookmark(out, i, 8)?;
+            }
+            writeln!(out, "    </DL><p>").infer_err()?;
+        }
+        for i in root {
+            self.html_bookmark(out, i, 4)?;
+        }
+        writeln!(out, "</DL><p>").infer_err()?;
+        Ok(())
+    }
+
+    fn html_bookmark(&self, out: &mut impl Write, i: usize, indent: usize) -> Result<(), Error> {
+        let bk = self.load(i)?;
+        let tags = self
+            .names_of(i)
+            .into_iter()
+            .map(|t| t.replace(',', " "))
+            .collect::<Vec<_>>()
+            .join(",");
+        let b = &bk.data.data;
+        write!(
+            out,
+            "{:indent$}<DT><A HREF=\"{}\" ADD_DATE=\"{}\" LAST_MODIFIED=\"{}\"",
+            "",
+            escape_html(&b.url),
+            bk.ctime,
+            bk.last_update,
+        )
+        .infer_err()?;
+        if !tags.is_empty() {
+            write!(out, " TAGS=\"{}\"", escape_html(&tags)).infer_err()?;
+        }
+        writeln!(
+            out,
+            ">{}</A>",
+            escape_html(b.title.as_deref().unwrap_or(&b.url))
+        )
+        .infer_err()?;
+        if let Some(d) = b.description.as_ref().filter(|d| !d.is_empty()) {
+            writeln!(out, "{:indent$}<DD>{}", "", escape_html(d)).infer_err()?;
+        }
+        Ok(())
+    }
+
+    // a json array of Meta<Tagged<Bookmark>>
+    fn json(&self, out: &mut impl Write) -> Result<(), Error> {
+        out.write_all(b"[").infer_err()?;
+        for i in 0..self.addresses.len() {
+            let bk = self.load(i)?;
+            let bk = Meta {
+                id: bk.id,
+                facet: bk.facet,
+                data: Tagged {
+                    data: bk.data.data,
+                    tags: self.names_of(i).into_iter().map(String::from).collect(),
+                },
+                ctime: bk.ctime,
+                last_update: bk.last_update,
+                last_interaction: bk.last_interaction,
+            };
+            if i != 0 {
+                out.write_all(b",").infer_err()?;
+            }
+            serde_json::to_writer(&mut *out, &bk).infer_err()?;
+        }
+        out.write_all(b"]").infer_err()?;
+        Ok(())
+    }
+
+    fn csv(&self, out: &mut impl Write) -> Result<(), Error> {
+        writeln!(out, "id,url,title,description,tags,ctime,last_update").infer_err()?;
+        for i in 0..self.addresses.len() {
+            let bk = self.load(i)?;
+            let b = &bk.data.data;
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{}",
+                bk.id,
+                escape_csv(&b.url),
+                escape_csv(b.title.as_deref().unwrap_or_default()),
+                escape_csv(b.description.as_deref().unwrap_or_default()),
+                escape_csv(&self.names_of(i).join(",")),
+                bk.ctime,
+                bk.last_update,
+            )
+            .infer_err()?;
+        }
+        Ok(())
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[derive(Default)]
+struct TagTree<'a> {
+    children: BTreeMap<(usize, &'a str), TagTree<'a>>,
+    bookmarks: Vec<&'a Bookmark>,
+}
+
+/*
+# tag
+  # tag + tag
+    - [link title](link url)
+  - [link title](link url)
+- https://somelink
+*/
+// - bookmarks are grouped by their tags. the most used tags become the outermost headings
+// - headings with a single sub heading and no bookmarks are merged into 'tag + tag'
+// - '\' escapes the characters that the parser would otherwise read as markup
+pub fn bookmarks_to_markdown(bookmarks: &[TaggedBookmark], names: &HashMap<Id, String>) -> String {
+    let mut usage = HashMap::<&str, usize>::new();
+    let bookmarks = bookmarks
+        .iter()
+        .map(|b| {
+            let mut tags = b
+                .data
+                .tags
+                .iter()
+                .filter_map(|t| names.get(t))
+                .map(|t| t.trim())
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>();
+            tags.sort_unstable();
+            tags.dedup();
+            for t in tags.iter() {
+                *usage.entry(t).or_default() += 1;
+            }
+            (tags, &b.data.data)
+        })
+        .collect::<Vec<_>>();
+
+    let mut root = TagTree::default();
+    for (mut tags, bk) in bookmarks {
+        tags.sort_by_key(|t| (usize::MAX - usage[t], *t));
+        let mut node = &mut root;
+        for t in tags {
+            node = node.children.entry((usize::MAX - usage[t], t)).or_default();
+        }
+        node.bookmarks.push(bk);
+    }
+
+    let mut md = String::new();
+    write_tree(&root, 0, &mut md);
+    md
+}
+
+fn write_tree(node: &TagTree, indent: usize, md: &mut String) {
+    for bk in node.bookmarks.iter() {
+        md.push_str(&" ".repeat(indent));
+        md.push_str(&bookmark_line(bk));
+        md.push('\n');
+    }
+    for (&(_, tag), mut child) in node.children.iter() {
+        let mut heading = vec![tag];
+        while child.bookmarks.is_empty() && child.children.len() == 1 {
+            let (&(_, tag), c) = child.children.iter().next().expect("len is 1");
+            heading.push(tag);
+            child = c;
+        }
+        md.push_str(&" ".repeat(indent));
+        md.push_str("# ");
+        md.push_str(
+            &heading
+                .into_iter()
+                .map(heading_tag)
+                .collect::<Vec<_>>()
+                .join(" + "),
+        );
+        md.push('\n');
+        write_tree(child, indent + 2, md);
+    }
+}
+
+// escaped so that bookmarks::bookmark_from_markdown_url reads back the same title and url.
+// a title can't span lines, so line breaks become spaces
+fn bookmark_line(bk: &Bookmark) -> String {
+    let title = bk
+        .title
+        .as_deref()
+        .unwrap_or_default()
+        .replace(['\n', '\r'], " ");
+    format!(
+        "- [{}]({})",
+        escape_markdown(&title, &['[', ']']),
+        escape_markdown(&bk.url, &['(', ')'])
+    )
+}
+
+// see bookmarks::split_tags. edge whitespace is trimmed from all tags before this
+fn heading_tag(tag: &str) -> String {
+    let tag = escape_markdown(tag, &['+', '/']);
+    match tag.starts_with(['#', '-']) {
+        true => format!("\\{}", tag),
+        false => tag,
+    }
+}
+
+fn escape_markdown(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bookmarks::{bookmark_from_markdown_url, tagged_strings_from_text},
+        database::tests::{add_object, commit, test_db, test_object},
+    };
+
+    fn bookmark(id: Id, url: &str, title: Option<&str>, tags: Vec<Id>) -> TaggedBookmark {
+        Meta {
+            id,
+            facet: TypeFacet::Bookmark,
+            data: Taggable {
+                data: Bookmark {
+                    url: url.into(),
+                    title: title.map(String::from),
+                    description: None,
+                    source: None,
+                    link: None,
+                    archive: None,
+                    crawled: None,
+                    image: None,
+                    icon: None,
+                    site_name: None,
+                    canonical: None,
+                    source_tags: None,
+                },
+                tags,
+            },
+            ctime: id as _,
+            last_update: id as _,
+            last_interaction: id as _,
+        }
+    }
+
+    #[test]
+    fn markdown_round_trip() {
+        let names = [
+            "rust",
+            "c++",
+            "tcp/ip",
+            "#hash",
+            "-dash",
+            "back\\slash",
+            "a + b",
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, n)| (i as Id, n.to_string()))
+        .collect::<HashMap<_, _>>();
+        let bookmarks = vec![
+            bookmark(
+                0,
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+                Some("Rust (programming language)"),
+                vec![0],
+            ),
+            bookmark(
+                1,
+                "https://example.com/a",
+                Some("  [brackets] and ](parens)  "),
+                vec![1, 2],
+            ),
+            bookmark(
+                2,
+                "https://example.com/b",
+                Some("C:\\path\\to\\file \\[x\\]"),
+                vec![3, 4, 5],
+            ),
+            bookmark(3, "https://example.com/c", None, vec![6, 0]),
+            bookmark(4, "https://example.com/d", Some("no tags"), vec![]),
+        ];
+
+        let md = bookmarks_to_markdown(&bookmarks, &names);
+        let (lines, failed) = tagged_strings_from_text(&md);
+        assert!(failed.is_empty(), "{:?}", failed);
+
+        let mut parsed = lines
+            .into_iter()
+            .map(|l| {
+                let b = bookmark_from_markdown_url(&l.data).expect(&l.data);
+                let mut tags = l.tags;
+                tags.sort();
+                (b.url, b.title, tags)
+            })
+            .collect::<Vec<_>>();
+        parsed.sort();
+        let mut expected = bookmarks
+            .iter()
+            .map(|b| {
+                let mut tags = b
+                    .data
+                    .tags
+                    .iter()
+                    .map(|t| names[t].clone())
+                    .collect::<Vec<_>>();
+                tags.sort();
+                (b.data.data.url.clone(), b.data.data.title.clone(), tags)
+            })
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(parsed, expected, "{}", md);
+    }
+
+    #[tokio::test]
+    async fn every_tag_is_exported() {
+        let (_dir, db) = test_db().await;
+        add_object(&db, test_object(&db, "a", vec![1, 2], 0));
+        add_object(&db, test_object(&db, "b", vec![], 0));
+        commit(&db);
+
+        let searcher = db.get_searcher();
+        let addresses = bookmark_addresses(&db, &searcher, "", vec![], None).unwrap();
+        let mut tags = bookmark_tags(&db, &searcher, &addresses).unwrap();
+        tags.sort();
+        assert_eq!(tags, [vec![], vec![1, 2]] as [Vec<Id>; 2]);
+    }
+}
@@ -80,7 +80,7 @@ pub mod thumbnails {
                             .collect(),
                     };
 
-                    let facet = TypeFacet::Temp("/cache/thumbnails_cache".into()).facet();
+                    let facet = TypeFacet::Internal("/cache/thumbnails_cache".into()).facet();
 
                     let mut writer = db.index_writer.write().unwrap();
                     let _opstamp =
@@ -342,7 +342,7 @@ pub mod thumbnails {
                     &TermQuery::new(
                         Term::from_facet(
                             db.get_field(Fields::Type),
-                            &TypeFacet::Temp("/cache/thumbnails_cache".into()).facet(),
+                            &TypeFacet::Internal("/cache/thumbnails_cache".into()).facet(),
                         ),
                         IndexRecordOption::Basic,
                     ),
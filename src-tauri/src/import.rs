@@ -0,0 +1,789 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// importers for bookmarks exported from / stored by browsers.
+// - folders become tags. nested folders become one tag per folder
+// - bookmarks that already exist (same canonical url) only get the new tags
+
+use std::{
+    collections::HashMap,
+    path::{Path as FsPath, PathBuf},
+    str::FromStr,
+};
+
+use kolekk_types::{
+    objects::{
+        Bookmark, BookmarkSourceFormat, Fields, Id, ImportReport, Indexed, Tagged, WithContext,
+    },
+    utility::Path,
+};
+use rusqlite::{Connection, OpenFlags};
+use serde::Deserialize;
+use serde_json::Value;
+use tantivy::{Document, Term};
+use tauri::{http::Uri, AppHandle, State};
+
+use crate::{
+    archive::archive_on_add,
+    bad_error::{BadError, Error, InferBadError},
+    config::AppConfig,
+    database::{add_or_search_tag, AppDatabase, DbAble, IntoRObject},
+    filesystem::get_path,
+    sources::import_source,
+    urls::{canonical_url, tracking_params},
+    watch::SourceWatcher,
+};
+
+pub struct ImportedBookmark {
+    pub bookmark: Tagged<Bookmark>,
+    // unix secs. when it was added in the browser
+    pub ctime: Option<u64>,
+    // browser keywords for the bookmark. these are made searchable
+    pub keywords: Vec<String>,
+}
+
+#[tauri::command]
+pub async fn import_netscape_bookmarks(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+    path: Path,
+) -> Result<ImportReport, Error> {
+    let html = tokio::fs::read_to_string(get_path(&path, config.inner()))
+        .await
+        .infer_err()?;
+    let (report, added) = save_imported(db.inner(), parse_netscape(&html))?;
+    archive_on_add(app.inner(), added)?;
+    Ok(report)
+}
+
+// path to a places.sqlite file of a firefox profile
+#[tauri::command]
+pub async fn import_firefox_bookmarks(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+    path: Path,
+) -> Result<ImportReport, Error> {
+    let places = get_path(&path, config.inner());
+    let bookmarks = tokio::task::spawn_blocking({
+        let cache = config.app_cache_dir.clone();
+        move || read_firefox_places(&places, &cache)
+    })
+    .await
+    .infer_err()??;
+    let (report, added) = save_imported(db.inner(), bookmarks)?;
+    archive_on_add(app.inner(), added)?;
+    Ok(report)
+}
+
+// returns the ids of the bookmarks that were added
+pub fn save_imported(
+    db: &AppDatabase,
+    bookmarks: Vec<ImportedBookmark>,
+) -> Result<(ImportReport, Vec<Id>), Error> {
+    let mut report = ImportReport::default();
+    let merged = merge_duplicates(bookmarks, &tracking_params(db)?, &mut report.failed);
+
+    // tags created in this import can't be searched before the commit
+    let mut tags = HashMap::<String, Id>::new();
+    let mut added = vec![];
+    for b in merged {
+        let url = b.bookmark.data.url.clone();
+        match save_bookmark(db, b, &mut tags) {
+            Ok(Saved::Added(id)) => {
+                report.added += 1;
+                added.push(id);
+            }
+            Ok(Saved::Updated(_) | Saved::Unchanged(_)) => report.updated += 1,
+            Err(e) => report.failed.push(WithContext {
+                data: url,
+                context: e.0.into_owned(),
+            }),
+        }
+    }
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok((report, added))
+}
+
+// the same url can be in multiple folders. invalid urls are left out
+pub fn merge_duplicates(
+    bookmarks: Vec<ImportedBookmark>,
+    tracking: &[String],
+    failed: &mut Vec<WithContext<String, String>>,
+) -> Vec<ImportedBookmark> {
+    let mut merged = Vec::<ImportedBookmark>::new();
+    let mut index = HashMap::<String, usize>::new();
+    for b in bookmarks {
+        if let Err(e) = Uri::from_str(&b.bookmark.data.url) {
+            failed.push(WithContext {
+                data: b.bookmark.data.url,
+                context: e.to_string(),
+            });
+            continue;
+        }
+        let key = canonical_url(&b.bookmark.data.url, tracking);
+        match index.get(&key) {
+            Some(&i) => {
+                let m = &mut merged[i];
+                for t in b.bookmark.tags {
+                    if !m.bookmark.tags.contains(&t) {
+                        m.bookmark.tags.push(t);
+                    }
+                }
+                m.ctime = match (m.ctime, b.ctime) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                for k in b.keywords {
+                    if !m.keywords.contains(&k) {
+                        m.keywords.push(k);
+                    }
+                }
+                let (m, b) = (&mut m.bookmark.data, b.bookmark.data);
+                if m.title.is_none() {
+                    m.title = b.title;
+                }
+                if m.description.is_none() {
+                    m.description = b.description;
+                }
+            }
+            None => {
+                let _ = index.insert(key, merged.len());
+                merged.push(b);
+            }
+        }
+    }
+    merged
+}
+
+pub enum Saved {
+    Added(Id),
+    Updated(Id),
+    Unchanged(Id),
+}
+
+pub fn save_bookmark(
+    db: &AppDatabase,
+    b: ImportedBookmark,
+    tags: &mut HashMap<String, Id>,
+) -> Result<Saved, Error> {
+    let mut bk = b.bookmark.data.into_robject(db)?;
+    let existed = db.get_doc_address(bk.id).is_ok();
+
+    let mut changed = !existed;
+    let tag_ids = tag_ids(db, b.bookmark.tags, tags)?;
+    for &id in tag_ids.iter() {
+        if !bk.data.data.tags.contains(&id) {
+            bk.data.data.tags.push(id);
+            changed = true;
+        }
+    }
+    // a bookmark that existed before keeps the tags as its own
+    if !existed && bk.data.data.data.source.is_some() {
+        bk.data.data.data.source_tags = Some(tag_ids);
+    }
+    for k in b.keywords {
+        let k = Value::String(k);
+        if !bk.data.searchable.iter().any(|i| i.data == k) {
+            bk.data.searchable.push(Indexed {
+                field: Fields::Text,
+                data: k,
+            });
+            changed = true;
+        }
+    }
+    if let Some(t) = b.ctime.filter(|&t| t < bk.ctime) {
+        bk.ctime = t;
+        changed = true;
+    }
+    if !changed {
+        return Ok(Saved::Unchanged(bk.id));
+    }
+    if existed {
+        bk.last_update = db.now_time()?;
+    }
+
+    let id = bk.id;
+    let mut doc = Document::new();
+    bk.add(db, &mut doc)?;
+    let writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    match existed {
+        true => Ok(Saved::Updated(id)),
+        false => Ok(Saved::Added(id)),
+    }
+}
+
+// tags created in this import can't be searched before the commit, so the ones created so
+// far are kept in created
+pub fn tag_ids(
+    db: &AppDatabase,
+    names: Vec<String>,
+    created: &mut HashMap<String, Id>,
+) -> Result<Vec<Id>, Error> {
+    let mut ids = vec![];
+    for name in names {
+        let id = match created.get(&name) {
+            Some(&id) => id,
+            None => {
+                let id = add_or_search_tag(db, name.clone())?.id;
+                let _ = created.insert(name, id);
+                id
+            }
+        };
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+// browsers store times in secs, millis or micros
+pub fn unix_secs(t: u64) -> u64 {
+    match t {
+        t if t > 100_000_000_000_000 => t / 1_000_000,
+        t if t > 100_000_000_000 => t / 1000,
+        t => t,
+    }
+}
+
+/*
+<DL><p>
+    <DT><H3 ADD_DATE="1690000000">folder</H3>
+    <DL><p>
+        <DT><A HREF="https://somelink" ADD_DATE="1690000000" TAGS="tag,tag">title</A>
+        <DD>description
+    </DL><p>
+</DL><p>
+*/
+// the format is not valid html, so it is parsed tag by tag
+pub fn parse_netscape(html: &str) -> Vec<ImportedBookmark> {
+    let mut bookmarks = Vec::<ImportedBookmark>::new();
+    // None for folders that should not become tags
+    let mut folders = Vec::<Option<String>>::new();
+    let mut next_folder = None;
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let (tag, attrs) = split_tag(&rest[..end]);
+        rest = &rest[end + 1..];
+        // text up to the next tag
+        let text = || unescape(rest[..rest.find('<').unwrap_or(rest.len())].trim());
+
+        match tag.as_str() {
+            "h3" => {
+                // the toolbar and 'other bookmarks' folders are not chosen by the user
+                let special = attrs.iter().any(|(k, _)| {
+                    k == "personal_toolbar_folder" || k == "unfiled_bookmarks_folder"
+                });
+                next_folder = Some(Some(text()).filter(|t| !t.is_empty() && !special));
+            }
+            "dl" => folders.push(next_folder.take().flatten()),
+            "/dl" => {
+                let _ = folders.pop();
+            }
+            "a" => {
+                let attr = |name: &str| {
+                    attrs
+                        .iter()
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.as_str())
+                };
+                let Some(url) = attr("href").filter(|u| !u.is_empty()) else {
+                    continue;
+                };
+                // firefox smart bookmarks and bookmarklets
+                if url.starts_with("place:") || url.starts_with("javascript:") {
+                    continue;
+                }
+                let mut tags = folders.iter().flatten().cloned().collect::<Vec<_>>();
+                for t in attr("tags").unwrap_or_default().split(',') {
+                    let t = t.trim();
+                    if !t.is_empty() && !tags.iter().any(|e| e == t) {
+                        tags.push(t.to_owned());
+                    }
+                }
+                let title = text();
+                bookmarks.push(ImportedBookmark {
+                    bookmark: Tagged {
+                        data: Bookmark {
+                            title: Some(title).filter(|t| !t.is_empty()),
+                            url: url.to_owned(),
+                            description: attr("description")
+                                .filter(|d| !d.is_empty())
+                                .map(ToOwned::to_owned),
+                            source: None,
+                            link: None,
+                            archive: None,
+                            crawled: None,
+                            image: None,
+                            icon: None,
+                            site_name: None,
+                            canonical: None,
+                            source_tags: None,
+                        },
+                        tags,
+                    },
+                    ctime: attr("add_date").and_then(|d| d.parse().ok()).map(unix_secs),
+                    keywords: attr("shortcuturl")
+                        .filter(|k| !k.is_empty())
+                        .map(ToOwned::to_owned)
+                        .into_iter()
+                        .collect(),
+                });
+            }
+            "dd" => {
+                if let Some(b) = bookmarks.last_mut() {
+                    let d = text();
+                    if b.bookmark.data.description.is_none() && !d.is_empty() {
+                        b.bookmark.data.description = Some(d);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    bookmarks
+}
+
+// returns the lowercase tag name and attributes with lowercase names
+fn split_tag(tag: &str) -> (String, Vec<(String, String)>) {
+    let tag = tag.trim();
+    let (name, mut rest) = tag.split_at(tag.find(char::is_whitespace).unwrap_or(tag.len()));
+    let mut attrs = vec![];
+    loop {
+        rest = rest.trim_start();
+        let Some(eq) = rest.find('=') else {
+            break;
+        };
+        let key = rest[..eq].trim().to_lowercase();
+        rest = rest[eq + 1..].trim_start();
+        let value = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => {
+                let v = &rest[1..];
+                let end = v.find(q).unwrap_or(v.len());
+                rest = v.get(end + 1..).unwrap_or_default();
+                &v[..end]
+            }
+            _ => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let v = &rest[..end];
+                rest = &rest[end..];
+                v
+            }
+        };
+        attrs.push((key, unescape(value)));
+    }
+    (name.to_lowercase(), attrs)
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+// firefox keeps the db locked while it is running, so a copy of it is read.
+// - bookmarks are rows in moz_bookmarks pointing to a url in moz_places
+// - tags are folders under the tags root. a bookmark in a tag folder tags its url
+fn read_firefox_places(
+    places: &FsPath,
+    cache_dir: &FsPath,
+) -> Result<Vec<ImportedBookmark>, Error> {
+    let copy_dir = cache_dir.join(format!("firefox_places_{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&copy_dir).infer_err()?;
+    let res = (|| {
+        let name = places.file_name().bad_err("bad places.sqlite path")?;
+        // recent changes might only be in the write ahead log
+        for suffix in ["", "-wal"] {
+            let mut from = name.to_owned();
+            from.push(suffix);
+            let from = places.with_file_name(from);
+            if from.exists() {
+                let mut to = PathBuf::from("places.sqlite").into_os_string();
+                to.push(suffix);
+                let _ = std::fs::copy(&from, copy_dir.join(to)).infer_err()?;
+            }
+        }
+        let conn = Connection::open_with_flags(
+            copy_dir.join("places.sqlite"),
+            OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )
+        .infer_err()?;
+        read_places(&conn)
+    })();
+    let _ = std::fs::remove_dir_all(&copy_dir).infer_err().dbg();
+    res
+}
+
+fn read_places(conn: &Connection) -> Result<Vec<ImportedBookmark>, Error> {
+    // folder id -> (parent id, title)
+    let mut folders = HashMap::<i64, (i64, String)>::new();
+    // roots (menu, toolbar, ..) are not chosen by the user
+    let mut roots = vec![];
+    let mut tags_root = None;
+    {
+        let mut stmt = conn
+            .prepare("SELECT id, parent, IFNULL(title, ''), guid FROM moz_bookmarks WHERE type = 2")
+            .infer_err()?;
+        let mut rows = stmt.query([]).infer_err()?;
+        while let Some(row) = rows.next().infer_err()? {
+            let id: i64 = row.get(0).infer_err()?;
+            let guid: String = row.get(3).infer_err()?;
+            if guid.ends_with("____") {
+                roots.push(id);
+                if guid == "tags________" {
+                    tags_root = Some(id);
+                }
+            }
+            let _ = folders.insert(id, (row.get(1).infer_err()?, row.get(2).infer_err()?));
+        }
+    }
+
+    // place id -> keywords
+    let mut keywords = HashMap::<i64, Vec<String>>::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT place_id, keyword FROM moz_keywords")
+            .infer_err()?;
+        let mut rows = stmt.query([]).infer_err()?;
+        while let Some(row) = rows.next().infer_err()? {
+            keywords
+                .entry(row.get(0).infer_err()?)
+                .or_default()
+                .push(row.get(1).infer_err()?);
+        }
+    }
+
+    // place id -> tags
+    let mut place_tags = HashMap::<i64, Vec<String>>::new();
+    let mut bookmarks = vec![];
+    let mut stmt = conn
+        .prepare(
+            "SELECT b.parent, b.fk, IFNULL(b.title, ''), b.dateAdded, p.url
+            FROM moz_bookmarks b JOIN moz_places p ON b.fk = p.id
+            WHERE b.type = 1",
+        )
+        .infer_err()?;
+    let mut rows = stmt.query([]).infer_err()?;
+    while let Some(row) = rows.next().infer_err()? {
+        let parent: i64 = row.get(0).infer_err()?;
+        let place: i64 = row.get(1).infer_err()?;
+        let title: String = row.get(2).infer_err()?;
+        let added: Option<i64> = row.get(3).infer_err()?;
+        let url: String = row.get(4).infer_err()?;
+
+        // bookmarks directly in a tag folder only mark the url with the tag
+        if let Some((grandparent, tag)) = folders.get(&parent) {
+            if Some(*grandparent) == tags_root {
+                place_tags.entry(place).or_default().push(tag.clone());
+                continue;
+            }
+        }
+        if url.starts_with("place:") || url.starts_with("javascript:") {
+            continue;
+        }
+
+        let mut path = vec![];
+        let mut folder = parent;
+        while let Some((p, title)) = folders.get(&folder) {
+            // the depth check guards against cycles in a corrupt db
+            if roots.contains(&folder) || path.len() > 100 {
+                break;
+            }
+            if !title.is_empty() {
+                path.push(title.clone());
+            }
+            folder = *p;
+        }
+        path.reverse();
+
+        bookmarks.push((
+            place,
+            ImportedBookmark {
+                bookmark: Tagged {
+                    data: Bookmark {
+                        title: Some(title).filter(|t| !t.is_empty()),
+                        url,
+                        description: None,
+                        source: None,
+                        link: None,
+                        archive: None,
+                        crawled: None,
+                        image: None,
+                        icon: None,
+                        site_name: None,
+                        canonical: None,
+                        source_tags: None,
+                    },
+                    tags: path,
+                },
+                ctime: added.map(|t| unix_secs(t.max(0) as _)),
+                keywords: keywords.get(&place).cloned().unwrap_or_default(),
+            },
+        ));
+    }
+
+    let bookmarks = bookmarks
+        .into_iter()
+        .map(|(place, mut b)| {
+            for t in place_tags.get(&place).into_iter().flatten() {
+                if !b.bookmark.tags.contains(t) {
+                    b.bookmark.tags.push(t.clone());
+                }
+            }
+            b
+        })
+        .collect();
+    Ok(bookmarks)
+}
+
+// path to the 'Bookmarks' file of a chromium profile.
+// if watch is true, the file is added as a BookmarkSource and reimported when it changes
+#[tauri::command]
+pub async fn import_chromium_bookmarks(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+    watcher: State<'_, SourceWatcher>,
+    path: Path,
+    title: String,
+    watch: bool,
+) -> Result<ImportReport, Error> {
+    if watch {
+        let pb = get_path(&path, config.inner());
+        let report = import_source(
+            app.inner(),
+            title,
+            path,
+            BookmarkSourceFormat::Chromium,
+            None,
+        )?;
+        watcher.watch(&pb)?;
+        return Ok(ImportReport {
+            added: report.added,
+            updated: report.changed,
+            failed: report.failed,
+        });
+    }
+    let json = std::fs::read_to_string(get_path(&path, config.inner())).infer_err()?;
+    let mut failed = vec![];
+    let bookmarks = parse_chromium(&json, &mut failed)?;
+    let (mut report, added) = save_imported(db.inner(), bookmarks)?;
+    report.failed.extend(failed);
+    archive_on_add(app.inner(), added)?;
+    Ok(report)
+}
+
+#[derive(Deserialize)]
+struct ChromiumBookmarks {
+    roots: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ChromiumNode {
+    Url {
+        name: String,
+        url: String,
+        date_added: Option<String>,
+    },
+    Folder {
+        name: String,
+        // read one by one, so that one bad node doesn't lose the whole folder
+        #[serde(default)]
+        children: Vec<Value>,
+    },
+}
+
+// {"roots": {"bookmark_bar": {"type": "folder", "name": "Bookmarks bar", "children": [
+//     {"type": "url", "name": "title", "url": "https://somelink", "date_added": "13300000000000000"},
+//     {"type": "folder", "name": "folder", "children": [..]}
+// ]}, "other": {..}, "synced": {..}}}
+pub fn parse_chromium(
+    json: &str,
+    failed: &mut Vec<WithContext<String, String>>,
+) -> Result<Vec<ImportedBookmark>, Error> {
+    let bookmarks: ChromiumBookmarks = serde_json::from_str(json).infer_err()?;
+    let mut res = vec![];
+    // roots also has a 'sync_transaction_version' string in some versions
+    for root in bookmarks.roots.into_values().filter(|r| r.is_object()) {
+        if let Some(ChromiumNode::Folder { children, .. }) = chromium_node(root, failed) {
+            // the root folders are not chosen by the user
+            chromium_nodes(children, &mut vec![], &mut res, failed);
+        }
+    }
+    Ok(res)
+}
+
+// nodes that can't be read are pushed to failed
+fn chromium_node(
+    node: Value,
+    failed: &mut Vec<WithContext<String, String>>,
+) -> Option<ChromiumNode> {
+    let data = node
+        .get("url")
+        .or_else(|| node.get("name"))
+        .and_then(|n| n.as_str())
+        .map(String::from)
+        .unwrap_or_else(|| node.to_string());
+    match serde_json::from_value(node) {
+        Ok(node) => Some(node),
+        Err(e) => {
+            failed.push(WithContext {
+                data,
+                context: e.to_string(),
+            });
+            None
+        }
+    }
+}
+
+fn chromium_nodes(
+    nodes: Vec<Value>,
+    folders: &mut Vec<String>,
+    res: &mut Vec<ImportedBookmark>,
+    failed: &mut Vec<WithContext<String, String>>,
+) {
+    for node in nodes {
+        let Some(node) = chromium_node(node, failed) else {
+            continue;
+        };
+        match node {
+            ChromiumNode::Url {
+                name,
+                url,
+                date_added,
+            } => res.push(ImportedBookmark {
+                bookmark: Tagged {
+                    data: Bookmark {
+                        title: Some(name).filter(|n| !n.is_empty()),
+                        url,
+                        description: None,
+                        source: None,
+                        link: None,
+                        archive: None,
+                        crawled: None,
+                        image: None,
+                        icon: None,
+                        site_name: None,
+                        canonical: None,
+                        source_tags: None,
+                    },
+                    tags: folders.clone(),
+                },
+                // microseconds since 1601-01-01
+                ctime: date_added
+                    .and_then(|d| d.parse::<u64>().ok())
+                    .map(|d| (d / 1_000_000).saturating_sub(11_644_473_600))
+                    .filter(|&d| d > 0),
+                keywords: vec![],
+            }),
+            ChromiumNode::Folder { name, children } => {
+                let named = !name.is_empty();
+                if named {
+                    folders.push(name);
+                }
+                chromium_nodes(children, folders, res, failed);
+                if named {
+                    let _ = folders.pop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kolekk_types::objects::TypeFacet;
+    use tantivy::{collector::Count, query::TermQuery, schema::IndexRecordOption};
+
+    use crate::database::{
+        tests::{commit, test_db},
+        FacetFrom,
+    };
+
+    const HTML: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><H3>Say &quot;hi&quot;</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/a">a</A>
+        <DT><H3>rust</H3>
+        <DL><p>
+            <DT><A HREF="https://www.rust-lang.org/" TAGS="lang,programming">Rust</A>
+        </DL><p>
+    </DL><p>
+</DL><p>
+"#;
+
+    fn tag_count(db: &AppDatabase) -> usize {
+        db.get_searcher()
+            .search(
+                &TermQuery::new(
+                    Term::from_facet(db.get_field(Fields::Type), &TypeFacet::Tag.facet()),
+                    IndexRecordOption::Basic,
+                ),
+                &Count,
+            )
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reimport_reuses_folder_tags() {
+        let (_dir, db) = test_db().await;
+
+        let (report, _added) = save_imported(&db, parse_netscape(HTML)).unwrap();
+        db.index_reader.reload().unwrap();
+        assert_eq!(report.added, 2);
+        assert!(report.failed.is_empty());
+        let tags = tag_count(&db);
+        assert_eq!(tags, 4);
+
+        let (report, _added) = save_imported(&db, parse_netscape(HTML)).unwrap();
+        commit(&db);
+        assert_eq!(report.added, 0);
+        assert!(report.failed.is_empty());
+        assert_eq!(tag_count(&db), tags);
+    }
+
+    #[test]
+    fn bad_chromium_nodes_are_reported() {
+        let json = r#"{"roots": {
+            "bookmark_bar": {"type": "folder", "name": "Bookmarks bar", "children": [
+                {"type": "url", "name": "Rust", "url": "https://www.rust-lang.org/"},
+                {"type": "url", "name": "no url"},
+                {"type": "folder", "name": "search", "children": [
+                    {"type": "url", "name": "tantivy", "url": "https://docs.rs/tantivy"}
+                ]}
+            ]},
+            "other": {"type": "folder", "name": "Other bookmarks", "children": []},
+            "sync_transaction_version": "1"
+        }}"#;
+        let mut failed = vec![];
+        let bookmarks = parse_chromium(json, &mut failed).unwrap();
+        let bookmarks = bookmarks
+            .into_iter()
+            .map(|b| (b.bookmark.data.url, b.bookmark.tags))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bookmarks,
+            [
+                ("https://www.rust-lang.org/".into(), vec![]),
+                ("https://docs.rs/tantivy".into(), vec!["search".into()]),
+            ] as [(String, Vec<String>); 2]
+        );
+        let failed = failed.into_iter().map(|f| f.data).collect::<Vec<_>>();
+        assert_eq!(failed, ["no url"]);
+    }
+}
@@ -0,0 +1,281 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - bookmarks are grouped by host. a few hosts are checked at the same time, but the
+//   links of a single host are checked one after the other with a delay in between.
+// - HEAD is tried first. some servers don't support it, so GET is used if it fails.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use kolekk_types::objects::{
+    BatchProgress, BatchResult, Bookmark, Fields, Id, LinkProblem, LinkStatus, Meta, Taggable,
+    TypeFacet,
+};
+use reqwest::Client;
+use serde_json::Value;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    bad_error::{BadError, Error, InferBadError},
+    batch::{objects_of_type, update_object, update_objects},
+    database::{AppDatabase, RObject},
+    urls::{bookmark_key, find_bookmark, set_bookmark_key, tracking_params},
+};
+
+const HOSTS_IN_PARALLEL: usize = 8;
+const HOST_DELAY: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Default)]
+pub struct LinkCheckState {
+    running: AtomicBool,
+}
+
+// checks the bookmarks that were not checked in the last recheck_after seconds (all if None)
+// in the background. progress is sent as 'batch-progress' events
+#[tauri::command]
+pub async fn start_link_check(
+    app: State<'_, AppHandle>,
+    state: State<'_, LinkCheckState>,
+    recheck_after: Option<u64>,
+) -> Result<(), Error> {
+    if state.running.swap(true, Ordering::SeqCst) {
+        return None.bad_err("link check is already running");
+    }
+    let handle = app.inner().app_handle();
+    let _ = tokio::task::spawn(async move {
+        let _ = check_links(&handle, recheck_after).await.dbg();
+        handle
+            .state::<LinkCheckState>()
+            .running
+            .store(false, Ordering::SeqCst);
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_link_problems(
+    db: State<'_, AppDatabase>,
+    problem: LinkProblem,
+) -> Result<Vec<Meta<Taggable<Bookmark>, TypeFacet>>, Error> {
+    let bookmarks: Vec<Meta<Taggable<Bookmark>, TypeFacet>> =
+        objects_of_type(db.inner(), TypeFacet::Bookmark)?;
+    let bookmarks = bookmarks
+        .into_iter()
+        .filter(|b| match (&b.data.data.link, problem) {
+            (Some(l), LinkProblem::Broken) => is_broken(l),
+            (Some(l), LinkProblem::Redirected) => !is_broken(l) && l.redirect.is_some(),
+            (None, _) => false,
+        })
+        .collect();
+    Ok(bookmarks)
+}
+
+// replaces the urls of the bookmarks with the urls they redirect to
+#[tauri::command]
+pub async fn apply_link_redirects(
+    db: State<'_, AppDatabase>,
+    app: State<'_, AppHandle>,
+    ids: Vec<Id>,
+) -> Result<Vec<BatchResult>, Error> {
+    let db = db.inner();
+    let tracking = tracking_params(db)?;
+    update_objects(db, app.inner(), "apply_redirects", ids, |o| {
+        let bk = &mut o.data.data.data;
+        let redirect = bk
+            .get("link")
+            .and_then(|l| l.get("redirect"))
+            .and_then(Value::as_str)
+            .map(String::from)
+            .bad_err("no redirect for this bookmark")?;
+        let key = bookmark_key(&redirect, None, &tracking);
+        if find_bookmark(db, &key)?
+            .map(|b| b.id != o.id)
+            .unwrap_or(false)
+        {
+            return None.bad_err("a bookmark with the redirected url already exists");
+        }
+        let old = bk.insert("url".into(), Value::String(redirect.clone()));
+        if let Some(Value::Object(l)) = bk.get_mut("link") {
+            let _ = l.insert("redirect".into(), Value::Null);
+        }
+        // the url is indexed as text
+        for i in o.data.data.searchable.iter_mut() {
+            if matches!(i.field, Fields::Text) && Some(&i.data) == old.as_ref() {
+                i.data = Value::String(redirect.clone());
+            }
+        }
+        set_bookmark_key(&mut o.data.data, &tracking);
+        Ok(())
+    })
+}
+
+pub fn is_broken(l: &LinkStatus) -> bool {
+    l.status.map(|s| s >= 400).unwrap_or(true)
+}
+
+async fn check_links(handle: &AppHandle, recheck_after: Option<u64>) -> Result<(), Error> {
+    let db = handle.state::<AppDatabase>();
+    let client = handle.state::<Client>().inner().clone();
+    let now = db.now_time()?;
+
+    let mut hosts = HashMap::<String, Vec<(Id, String)>>::new();
+    let bookmarks: Vec<Meta<Taggable<Bookmark>, TypeFacet>> =
+        objects_of_type(db.inner(), TypeFacet::Bookmark)?;
+    for b in bookmarks {
+        let checked = b.data.data.link.as_ref().map(|l| l.checked).unwrap_or(0);
+        if recheck_after.map(|r| checked + r > now).unwrap_or(false) {
+            continue;
+        }
+        let host = reqwest::Url::parse(&b.data.data.url)
+            .ok()
+            .and_then(|u| u.host_str().map(String::from))
+            .unwrap_or_default();
+        hosts.entry(host).or_default().push((b.id, b.data.data.url));
+    }
+
+    let total = hosts.values().map(Vec::len).sum::<usize>();
+    let done = AtomicUsize::new(0);
+    futures::stream::iter(hosts.into_values())
+        .map(|links| {
+            let (client, done, db) = (&client, &done, db.inner());
+            async move {
+                for (i, (id, url)) in links.into_iter().enumerate() {
+                    if i != 0 {
+                        tokio::time::sleep(HOST_DELAY).await;
+                    }
+                    let status = check_url(client, &url, db.now_time()?).await;
+                    let _ = update_object(db, id, |bk: &mut RObject<Bookmark>| {
+                        bk.data.data.data.link = Some(status);
+                        Ok(())
+                    })
+                    .dbg();
+
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    let _ = handle
+                        .emit_all(
+                            "batch-progress",
+                            BatchProgress {
+                                operation: "check_links".into(),
+                                done,
+                                total,
+                            },
+                        )
+                        .infer_err()
+                        .dbg();
+                }
+                Ok::<_, Error>(())
+            }
+        })
+        .buffer_unordered(HOSTS_IN_PARALLEL)
+        .for_each(|r| async move {
+            let _ = r.dbg();
+        })
+        .await;
+    Ok(())
+}
+
+// does not touch the database, so it can be pointed at any server
+pub async fn check_url(client: &Client, url: &str, now: u64) -> LinkStatus {
+    let mut res = client.head(url).timeout(TIMEOUT).send().await;
+    let retry = match res.as_ref() {
+        Ok(r) => r.status().is_client_error() || r.status().is_server_error(),
+        Err(_) => true,
+    };
+    if retry {
+        res = client.get(url).timeout(TIMEOUT).send().await;
+    }
+    match res {
+        Ok(r) => LinkStatus {
+            status: Some(r.status().as_u16()),
+            error: None,
+            // the url given is not normalized, so they are compared parsed
+            redirect: (reqwest::Url::parse(url).ok().as_ref() != Some(r.url()))
+                .then(|| r.url().to_string()),
+            checked: now,
+        },
+        Err(e) => LinkStatus {
+            status: e.status().map(|s| s.as_u16()),
+            error: Some(e.to_string()),
+            redirect: None,
+            checked: now,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // GET /, /ok, /missing, /redirect (to /ok) and /no-head (405 for HEAD)
+    async fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _ = tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let _ = tokio::spawn(async move {
+                    let mut req = vec![];
+                    let mut buf = [0; 1024];
+                    while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let req = String::from_utf8_lossy(&req);
+                    let mut line = req.split_whitespace();
+                    let (method, path) = (line.next().unwrap(), line.next().unwrap());
+                    let (status, extra) = match (method, path) {
+                        ("HEAD", "/no-head") => ("405 Method Not Allowed", ""),
+                        (_, "/" | "/ok" | "/no-head") => ("200 OK", ""),
+                        (_, "/redirect") => ("301 Moved Permanently", "Location: /ok\r\n"),
+                        _ => ("404 Not Found", ""),
+                    };
+                    let res = format!(
+                        "HTTP/1.1 {}\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        status, extra
+                    );
+                    let _ = stream.write_all(res.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn check_urls() {
+        let base = serve().await;
+        let client = Client::new();
+
+        let ok = check_url(&client, &format!("{}/ok", base), 1).await;
+        assert_eq!((ok.status, ok.redirect, ok.checked), (Some(200), None, 1));
+        assert!(!is_broken(&ok));
+
+        let missing = check_url(&client, &format!("{}/missing", base), 1).await;
+        assert_eq!(missing.status, Some(404));
+        assert!(is_broken(&missing));
+
+        let redirect = check_url(&client, &format!("{}/redirect", base), 1).await;
+        assert_eq!(redirect.status, Some(200));
+        assert_eq!(redirect.redirect, Some(format!("{}/ok", base)));
+
+        let no_head = check_url(&client, &format!("{}/no-head", base), 1).await;
+        assert_eq!((no_head.status, no_head.redirect), (Some(200), None));
+
+        // reqwest adds the trailing slash
+        let host = check_url(&client, &base, 1).await;
+        assert_eq!((host.status, host.redirect), (Some(200), None));
+    }
+}
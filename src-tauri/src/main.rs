@@ -6,16 +6,32 @@
 // #![allow(unused_imports)]
 
 mod api;
+mod archive;
+mod backup;
 mod bad_error;
+mod batch;
 mod bookmarks;
 mod config;
+mod crawl;
 mod database;
+mod export;
 mod filesystem;
 mod logg;
 // mod orm;
 mod clipboard;
 mod images;
+mod import;
+mod links;
+mod saved_search;
+mod sources;
+mod stats;
+mod suggest;
+mod sync;
 mod tag;
+mod temp;
+mod tokenizer;
+mod urls;
+mod watch;
 
 #[cfg(feature = "music")]
 mod player;
@@ -53,6 +69,19 @@ fn main() {
             bookmarks::bookmarks_from_html,
             bookmarks::get_bookmarks,
             bookmarks::search_bookmarks,
+            crawl::refresh_bookmark_content,
+            import::import_netscape_bookmarks,
+            import::import_firefox_bookmarks,
+            import::import_chromium_bookmarks,
+            export::export_bookmarks_markdown,
+            export::export_bookmarks,
+            links::start_link_check,
+            links::list_link_problems,
+            links::apply_link_redirects,
+            archive::archive_bookmarks,
+            archive::open_bookmark_archive,
+            archive::get_archive_config,
+            archive::set_archive_config,
             bookmarks::add_bookmark_source,
             bookmarks::refresh_bookmark_sources,
             images::get_images,
@@ -65,6 +94,9 @@ fn main() {
             images::thumbnails::whatever_thumbnail,
             images::thumbnails::get_thumbnail_size,
             tag::search_tags,
+            tag::autocomplete_tags,
+            tag::update_tag_metadata,
+            suggest::suggest_tags,
             tag::save_new_tag,
             tag::get_tags_from_ids,
             api::commands::search_tmdb_multi,
@@ -95,10 +127,32 @@ fn main() {
             database::enter_searchable,
             database::enter_searchable_item,
             database::search_jsml_object,
+            database::search_related_objects,
+            saved_search::save_search,
+            saved_search::update_saved_search,
+            saved_search::get_saved_searches,
+            saved_search::open_saved_search,
+            batch::add_tags_to_objects,
+            batch::remove_tags_from_objects,
+            batch::delete_objects,
+            batch::move_objects_to_group,
+            batch::export_objects,
+            stats::library_stats,
+            backup::list_backups,
+            backup::create_backup,
+            backup::restore_backup,
+            sync::enable_sync,
+            sync::disable_sync,
+            sync::sync_now,
             database::add_tag_to_object,
             database::remove_tag_from_object,
             database::delete_facet_objects,
-            database::new_temp_facet,
+            temp::new_temp_facet,
+            temp::set_temp_facet_ttl,
+            temp::promote_temp_facet,
+            urls::get_tracking_params,
+            urls::set_tracking_params,
+            urls::merge_duplicate_bookmarks,
             database::get_path,
             database::reload_reader,
             clipboard::copy_image_to_clipboard,
@@ -168,11 +222,18 @@ async fn setup(app_handle: &tauri::AppHandle) -> Result<(), Error> {
     );
     app_handle.manage(client.clone());
     app_handle.manage(clipboard::Clipboard::new()?);
+    app_handle.manage(stats::StatsCache::default());
+    app_handle.manage(links::LinkCheckState::default());
 
     database::init_database(app_handle, &conf).await?;
     let db = app_handle.state::<AppDatabase>().inner();
+    backup::init_backups(app_handle, &conf);
+    sync::init_sync(app_handle, db)?;
+    temp::init_temp_facets(app_handle, db)?;
+    archive::init_archive(app_handle, db)?;
 
     images::thumbnails::init_thumbnailer(app_handle, &conf, db, client.clone()).await?;
+    watch::init_source_watcher(app_handle, &conf, db)?;
 
     app_handle.manage(conf);
     Ok(())
@@ -0,0 +1,234 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+use std::ops::Bound;
+
+use kolekk_types::objects::{
+    Fields, Id, Indexed, Meta, SavedSearch, SearchFilters, SearchSort, SearchableEntry, TypeFacet,
+    WithContext,
+};
+use tantivy::{
+    collector::Count,
+    query::{BooleanQuery, Occur, RangeQuery},
+    Document, Term,
+};
+use tauri::State;
+
+use crate::{
+    bad_error::{Error, InferBadError},
+    database::{
+        sorted_query_search, sorted_search, tagged_match_query, AppDatabase, DbAble, JsmlObject,
+    },
+};
+
+#[tauri::command]
+pub async fn save_search(
+    db: State<'_, AppDatabase>,
+    title: String,
+    query: String,
+    facet: TypeFacet,
+    filters: SearchFilters,
+    sort: SearchSort,
+) -> Result<Id, Error> {
+    let db = db.inner();
+    let time = db.now_time()?;
+    let count = count_results(db, facet.clone(), &query, &filters)?;
+    let id = db.new_id();
+    let v = Meta {
+        id,
+        facet: TypeFacet::SavedSearch,
+        data: SearchableEntry {
+            searchable: vec![Indexed {
+                field: Fields::Text,
+                data: title.clone().into(),
+            }],
+            data: SavedSearch {
+                title,
+                query,
+                facet,
+                filters,
+                sort,
+                cached_count: Some(count),
+                last_opened: time,
+            },
+        },
+        ctime: time,
+        last_update: time,
+        last_interaction: time,
+    };
+    let mut doc = Document::new();
+    v.add(db, &mut doc)?;
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn update_saved_search(
+    db: State<'_, AppDatabase>,
+    id: Id,
+    title: String,
+    query: String,
+    filters: SearchFilters,
+    sort: SearchSort,
+) -> Result<(), Error> {
+    let db = db.inner();
+    let mut s = get_saved_search(db, id)?;
+    s.last_update = db.now_time()?;
+    s.data.searchable = vec![Indexed {
+        field: Fields::Text,
+        data: title.clone().into(),
+    }];
+    s.data.data.title = title;
+    s.data.data.query = query;
+    s.data.data.filters = filters;
+    s.data.data.sort = sort;
+    s.data.data.cached_count = None;
+    replace_saved_search(db, s)
+}
+
+/// context is the number of objects added since the search was last opened
+#[tauri::command]
+pub async fn get_saved_searches(
+    db: State<'_, AppDatabase>,
+) -> Result<Vec<WithContext<Meta<SavedSearch, TypeFacet>, u64>>, Error> {
+    let db = db.inner();
+    let searches: Vec<Meta<SavedSearch, TypeFacet>> = sorted_search(
+        db,
+        TypeFacet::SavedSearch,
+        "",
+        &SearchFilters::default(),
+        SearchSort::RecentlyViewed,
+        10000,
+        0,
+    )?;
+    searches
+        .into_iter()
+        .map(|s| {
+            let new = count_new_results(db, &s.data)?;
+            Ok(WithContext {
+                data: s,
+                context: new,
+            })
+        })
+        .collect()
+}
+
+// evaluates the search live. opening the first page marks the search as opened
+#[tauri::command]
+pub async fn open_saved_search(
+    db: State<'_, AppDatabase>,
+    id: Id,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<JsmlObject>, Error> {
+    let db = db.inner();
+    let mut s = get_saved_search(db, id)?;
+    let search = &s.data.data;
+    let q = tagged_match_query(db, search.facet.clone(), &search.query, &search.filters)?;
+    let res = sorted_query_search(db, &q, search.sort, limit, offset)?;
+
+    if offset == 0 {
+        let time = db.now_time()?;
+        s.data.data.cached_count = Some(count_results(
+            db,
+            s.data.data.facet.clone(),
+            &s.data.data.query,
+            &s.data.data.filters,
+        )?);
+        s.data.data.last_opened = time;
+        s.last_interaction = time;
+        replace_saved_search(db, s)?;
+    }
+
+    Ok(res)
+}
+
+fn get_saved_search(
+    db: &AppDatabase,
+    id: Id,
+) -> Result<Meta<SearchableEntry<SavedSearch>, TypeFacet>, Error> {
+    let mut doc = db.get_doc(id)?;
+    DbAble::take(db, &mut doc)
+}
+
+fn replace_saved_search(
+    db: &AppDatabase,
+    s: Meta<SearchableEntry<SavedSearch>, TypeFacet>,
+) -> Result<(), Error> {
+    let id = s.id;
+    let mut doc = Document::new();
+    s.add(db, &mut doc)?;
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+fn count_results(
+    db: &AppDatabase,
+    facet: TypeFacet,
+    query: &str,
+    filters: &SearchFilters,
+) -> Result<u64, Error> {
+    let q = tagged_match_query(db, facet, query, filters)?;
+    let count = db.get_searcher().search(&q, &Count).infer_err()?;
+    Ok(count as _)
+}
+
+fn count_new_results(db: &AppDatabase, search: &SavedSearch) -> Result<u64, Error> {
+    let q = tagged_match_query(db, search.facet.clone(), &search.query, &search.filters)?;
+    let q = BooleanQuery::new(vec![
+        (Occur::Must, Box::new(q)),
+        (
+            Occur::Must,
+            Box::new(RangeQuery::new_u64_bounds(
+                db.get_field(Fields::Ctime),
+                Bound::Excluded(search.last_opened),
+                Bound::Unbounded,
+            )),
+        ),
+    ]);
+    let count = db.get_searcher().search(&q, &Count).infer_err()?;
+    Ok(count as _)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{add_object, commit, test_db, test_object};
+
+    fn add(db: &AppDatabase, text: &str, ctime: u64) {
+        add_object(db, test_object(db, text, vec![], ctime));
+    }
+
+    #[tokio::test]
+    async fn counts_only_matching_objects() {
+        let (_dir, db) = test_db().await;
+        add(&db, "rust programming language", 10);
+        add(&db, "a recipe for pancakes", 10);
+        add(&db, "the rust book", 30);
+        commit(&db);
+
+        let filters = SearchFilters::default();
+        let count = |q: &str| count_results(&db, TypeFacet::Bookmark, q, &filters).unwrap();
+        assert_eq!(count(""), 3);
+        assert_eq!(count("rust"), 2);
+        assert_eq!(count("pancakes"), 1);
+
+        let search = SavedSearch {
+            title: "rust".into(),
+            query: "rust".into(),
+            facet: TypeFacet::Bookmark,
+            filters: SearchFilters::default(),
+            sort: SearchSort::Newest,
+            cached_count: None,
+            last_opened: 20,
+        };
+        assert_eq!(count_new_results(&db, &search).unwrap(), 1);
+    }
+}
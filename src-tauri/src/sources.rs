@@ -0,0 +1,891 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - a BookmarkSource is a file with bookmarks in it. its bookmarks have Bookmark::source set
+//   and get the title of the source as a tag.
+// - importing a source again diffs the file against the bookmarks of the source by canonical
+//   url. new entries are added, missing ones are deleted and the rest keep their id, ctime,
+//   interactions and the tags that were added by hand. only the tags in
+//   Bookmark::source_tags are replaced by the ones in the file.
+// - pages of new entries are not fetched while importing. the crawler fills in their titles
+//   in the background.
+// - every BookmarkSourceFormat has a BookmarkSourceParser in the parsers module.
+
+use std::{collections::HashMap, fs, os::unix::fs::MetadataExt, path::Path as FsPath};
+
+use kolekk_types::{
+    objects::{
+        Bookmark, BookmarkSource, BookmarkSourceFormat, Fields, Id, Indexed, Meta, SearchableEntry,
+        SourceRefreshReport, TypeFacet, WithContext,
+    },
+    utility::Path,
+};
+use serde_json::Value;
+use tantivy::{
+    collector::{DocSetCollector, TopDocs},
+    query::TermQuery,
+    schema::IndexRecordOption,
+    Document, Term,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    archive::{archive_on_add, delete_archive},
+    bad_error::{Error, InferBadError},
+    config::AppConfig,
+    crawl::crawl_on_add,
+    database::{delete_object, AppDatabase, DbAble, FacetFrom, RObject},
+    filesystem::get_path,
+    import::{merge_duplicates, save_bookmark, tag_ids, ImportedBookmark, Saved},
+    sources::parsers::parser,
+    urls::{canonical_url, tracking_params},
+};
+
+// pass the id of the source to import it again
+pub fn import_source(
+    app: &AppHandle,
+    title: String,
+    path: Path,
+    format: BookmarkSourceFormat,
+    id: Option<Id>,
+) -> Result<SourceRefreshReport, Error> {
+    let db = app.state::<AppDatabase>();
+    let db = db.inner();
+    let config = app.state::<AppConfig>();
+    let pb = get_path(&path, config.inner());
+    let mtime = source_mtime(&pb)?;
+    let mut failed = vec![];
+    let mut entries = read_source(&pb, format, &mut failed)?;
+
+    let source_id = id.unwrap_or_else(|| db.new_id());
+    for b in entries.iter_mut() {
+        b.bookmark.data.source = Some(source_id);
+        b.bookmark.tags.push(title.clone());
+    }
+
+    let time = db.now_time()?;
+    let source = Meta {
+        data: SearchableEntry {
+            searchable: vec![
+                Indexed {
+                    field: Fields::Text,
+                    data: title.clone().into(),
+                },
+                Indexed {
+                    field: Fields::Text,
+                    data: pb.to_string_lossy().to_string().into(),
+                },
+            ],
+            data: BookmarkSource {
+                title: title.clone(),
+                path,
+                last_checked: time,
+                mtime,
+                format,
+            },
+        },
+        facet: TypeFacet::BookmarkSource,
+        ctime: time,
+        last_update: time,
+        last_interaction: time,
+        id: source_id,
+    };
+    {
+        let mut doc = Document::new();
+        source.add(db, &mut doc)?;
+        let writer = db.index_writer.write().infer_err()?;
+        let _opstamp = writer.delete_term(Term::from_field_u64(
+            db.get_field(Fields::Id),
+            source_id as _,
+        ));
+        let _opstamp = writer.add_document(doc).infer_err()?;
+    }
+
+    let (mut report, added) = sync_source(db, config.inner(), source_id, title, entries)?;
+    report.failed.extend(failed);
+    let _opstamp = db.index_writer.write().infer_err()?.commit().infer_err()?;
+    crawl_on_add(app, added.clone());
+    archive_on_add(app, added)?;
+    Ok(report)
+}
+
+pub fn refresh_source(
+    app: &AppHandle,
+    source: Meta<BookmarkSource, TypeFacet>,
+) -> Result<SourceRefreshReport, Error> {
+    import_source(
+        app,
+        source.data.title,
+        source.data.path,
+        source.data.format,
+        Some(source.id),
+    )
+}
+
+pub fn bookmark_sources(db: &AppDatabase) -> Result<Vec<Meta<BookmarkSource, TypeFacet>>, Error> {
+    let searcher = db.get_searcher();
+    searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(
+                    db.get_field(Fields::Type),
+                    &TypeFacet::BookmarkSource.facet(),
+                ),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(10000),
+        )
+        .infer_err()?
+        .into_iter()
+        .map(|(_score, address)| {
+            let mut doc = searcher.doc(address).infer_err()?;
+            DbAble::take(db, &mut doc)
+        })
+        .collect()
+}
+
+// files that can't be read anymore are not modified. their bookmarks are kept
+pub fn source_modified(source: &Meta<BookmarkSource, TypeFacet>, config: &AppConfig) -> bool {
+    source_mtime(&get_path(&source.data.path, config))
+        .map(|mtime| mtime != source.data.mtime)
+        .unwrap_or(false)
+}
+
+// a directory (an obsidian vault) is read note by note
+fn read_source(
+    path: &FsPath,
+    format: BookmarkSourceFormat,
+    failed: &mut Vec<WithContext<String, String>>,
+) -> Result<Vec<ImportedBookmark>, Error> {
+    if !path.is_dir() {
+        let text = fs::read_to_string(path).infer_err()?;
+        return parser(format).parse(&text, failed);
+    }
+    let mut entries = vec![];
+    for file in vault_entries(path)
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| matches!(e.to_lowercase().as_str(), "md" | "markdown"))
+                .unwrap_or(false)
+        })
+    {
+        let text = match fs::read_to_string(file.path()) {
+            Ok(text) => text,
+            Err(e) => {
+                failed.push(WithContext {
+                    data: file.path().to_string_lossy().to_string(),
+                    context: e.to_string(),
+                });
+                continue;
+            }
+        };
+        entries.extend(parser(format).parse(&text, failed)?);
+    }
+    Ok(entries)
+}
+
+// the mtime of a directory is the newest one of the directories and files in it. removing a
+// note changes the mtime of its directory
+fn source_mtime(path: &FsPath) -> Result<i64, Error> {
+    if !path.is_dir() {
+        return Ok(fs::metadata(path).infer_err()?.mtime());
+    }
+    let mut mtime = 0;
+    for e in vault_entries(path) {
+        mtime = mtime.max(e.metadata().infer_err()?.mtime());
+    }
+    Ok(mtime)
+}
+
+// hidden directories like .obsidian and .git are skipped. obsidian writes to .obsidian all
+// the time
+fn vault_entries(path: &FsPath) -> impl Iterator<Item = walkdir::DirEntry> {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !e
+                    .file_name()
+                    .to_str()
+                    .map(|n| n.starts_with('.'))
+                    .unwrap_or(false)
+        })
+        .filter_map(|e| e.infer_err().dbg().ok())
+}
+
+// returns the ids of the bookmarks that were added
+fn sync_source(
+    db: &AppDatabase,
+    config: &AppConfig,
+    source: Id,
+    title: String,
+    entries: Vec<ImportedBookmark>,
+) -> Result<(SourceRefreshReport, Vec<Id>), Error> {
+    let tracking = tracking_params(db)?;
+    let mut report = SourceRefreshReport {
+        source,
+        title,
+        ..Default::default()
+    };
+    let entries = merge_duplicates(entries, &tracking, &mut report.failed);
+
+    let mut existing = HashMap::new();
+    let mut removed = vec![];
+    for bk in source_bookmarks(db, source)? {
+        let key = canonical_url(&bk.data.data.data.url, &tracking);
+        // the same url twice in a source is only possible from before urls were canonicalized
+        if let Some(old) = existing.insert(key, bk) {
+            removed.push(old.id);
+        }
+    }
+
+    let time = db.now_time()?;
+    let mut tags = HashMap::<String, Id>::new();
+    let mut added = vec![];
+    for b in entries {
+        let url = b.bookmark.data.url.clone();
+        let res = match existing.remove(&canonical_url(&url, &tracking)) {
+            Some(bk) => update_bookmark(db, bk, b, &mut tags, time).map(|changed| {
+                if changed {
+                    report.changed += 1;
+                }
+            }),
+            // the url might be bookmarked by hand or by another source. those bookmarks
+            // only get the new tags
+            None => save_bookmark(db, b, &mut tags).map(|saved| {
+                let id = match saved {
+                    Saved::Added(id) => {
+                        report.added += 1;
+                        added.push(id);
+                        return;
+                    }
+                    Saved::Updated(id) => {
+                        report.changed += 1;
+                        id
+                    }
+                    Saved::Unchanged(id) => id,
+                };
+                // the canonical url of the page can match a bookmark of this source with
+                // a different url
+                existing.retain(|_, bk| bk.id != id);
+            }),
+        };
+        if let Err(e) = res {
+            report.failed.push(WithContext {
+                data: url,
+                context: e.0.into_owned(),
+            });
+        }
+    }
+
+    removed.extend(existing.into_values().map(|bk| bk.id));
+    report.removed = removed.len();
+    let writer = db.index_writer.write().infer_err()?;
+    for id in removed {
+        delete_object(db, &writer, id, time);
+        let _ = delete_archive(config, id).dbg();
+    }
+    Ok((report, added))
+}
+
+// returns true if the bookmark changed
+fn update_bookmark(
+    db: &AppDatabase,
+    mut bk: RObject<Bookmark>,
+    b: ImportedBookmark,
+    tags: &mut HashMap<String, Id>,
+    time: u64,
+) -> Result<bool, Error> {
+    let new_tags = tag_ids(db, b.bookmark.tags, tags)?;
+    let (tagged, searchable) = (&mut bk.data.data, &mut bk.data.searchable);
+
+    // bookmarks from before source_tags existed don't know which of their tags came from the
+    // source, so none of their tags are removed
+    let old_source_tags = tagged.data.source_tags.replace(new_tags.clone());
+    let mut save = old_source_tags.as_ref() != Some(&new_tags);
+    let old_source_tags = old_source_tags.unwrap_or_default();
+    let old_tags = tagged.tags.clone();
+    tagged
+        .tags
+        .retain(|t| !old_source_tags.contains(t) || new_tags.contains(t));
+    for &t in new_tags.iter() {
+        if !tagged.tags.contains(&t) {
+            tagged.tags.push(t);
+        }
+    }
+    let mut changed = tagged.tags != old_tags;
+
+    let title = b.bookmark.data.title;
+    if let Some(title) = title.filter(|t| tagged.data.title.as_ref() != Some(t)) {
+        // the title is indexed as text too
+        if let Some(old) = tagged.data.title.replace(title.clone()) {
+            let old = Value::String(old);
+            searchable.retain(|i| !(matches!(i.field, Fields::Text) && i.data == old));
+        }
+        searchable.push(Indexed {
+            field: Fields::Text,
+            data: title.into(),
+        });
+        changed = true;
+    }
+    for k in b.keywords {
+        let k = Value::String(k);
+        if !searchable.iter().any(|i| i.data == k) {
+            searchable.push(Indexed {
+                field: Fields::Text,
+                data: k,
+            });
+            changed = true;
+        }
+    }
+
+    save |= changed;
+    if !save {
+        return Ok(false);
+    }
+    if changed {
+        bk.last_update = time;
+    }
+    let id = bk.id;
+    let mut doc = Document::new();
+    bk.add(db, &mut doc)?;
+    let writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    Ok(changed)
+}
+
+fn source_bookmarks(db: &AppDatabase, source: Id) -> Result<Vec<RObject<Bookmark>>, Error> {
+    let searcher = db.get_searcher();
+    searcher
+        .search(
+            &TermQuery::new(
+                Term::from_field_u64(db.get_field(Fields::SourceId), source as _),
+                IndexRecordOption::Basic,
+            ),
+            &DocSetCollector,
+        )
+        .infer_err()?
+        .into_iter()
+        .map(|address| {
+            let mut doc = searcher.doc(address).infer_err()?;
+            DbAble::take(db, &mut doc)
+        })
+        .collect()
+}
+
+pub mod parsers {
+    #[allow(unused_imports)]
+    use crate::{dbg, debug, error};
+
+    use std::{path::Path, str::FromStr};
+
+    use kolekk_types::objects::{Bookmark, BookmarkSourceFormat, Tagged, WithContext};
+    use tauri::http::Uri;
+
+    use crate::{
+        bad_error::Error,
+        bookmarks::{bookmark_from_markdown_url, tagged_strings_from_text},
+        import::{parse_chromium, ImportedBookmark},
+    };
+
+    // turns the text of a BookmarkSource into bookmarks. the title of the source is added as
+    // a tag to all of them later, and invalid urls are left out. entries that can't be read
+    // are left out too, and the ones worth telling the user about are pushed to failed
+    pub trait BookmarkSourceParser {
+        fn parse(
+            &self,
+            text: &str,
+            failed: &mut Vec<WithContext<String, String>>,
+        ) -> Result<Vec<ImportedBookmark>, Error>;
+    }
+
+    pub fn parser(format: BookmarkSourceFormat) -> &'static dyn BookmarkSourceParser {
+        match format {
+            BookmarkSourceFormat::Markdown => &MarkdownParser,
+            BookmarkSourceFormat::Chromium => &ChromiumParser,
+            BookmarkSourceFormat::Org => &OrgParser,
+            BookmarkSourceFormat::Obsidian => &ObsidianParser,
+            BookmarkSourceFormat::UrlList => &UrlListParser,
+        }
+    }
+
+    // obsidian notes are markdown files too. they are told apart by the .obsidian directory
+    // at the root of the vault. a directory is always read as a vault
+    pub fn detect_format(path: &Path) -> BookmarkSourceFormat {
+        if path.is_dir() {
+            return BookmarkSourceFormat::Obsidian;
+        }
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let in_vault = || {
+            path.ancestors()
+                .skip(1)
+                .any(|d| d.join(".obsidian").is_dir())
+        };
+        match ext.as_deref() {
+            Some("org") => BookmarkSourceFormat::Org,
+            Some("txt" | "urls" | "list") => BookmarkSourceFormat::UrlList,
+            Some("md" | "markdown") if in_vault() => BookmarkSourceFormat::Obsidian,
+            None if path.file_name().map(|n| n == "Bookmarks").unwrap_or(false) => {
+                BookmarkSourceFormat::Chromium
+            }
+            _ => BookmarkSourceFormat::Markdown,
+        }
+    }
+
+    pub struct MarkdownParser;
+    pub struct ChromiumParser;
+    pub struct OrgParser;
+    pub struct ObsidianParser;
+    pub struct UrlListParser;
+
+    // see tagged_strings_from_text. the pages are not fetched, so lines with a bare url get no
+    // title. lines that are not links are left out
+    impl BookmarkSourceParser for MarkdownParser {
+        fn parse(
+            &self,
+            text: &str,
+            _failed: &mut Vec<WithContext<String, String>>,
+        ) -> Result<Vec<ImportedBookmark>, Error> {
+            let bookmarks = tagged_strings_from_text(text)
+                .0
+                .into_iter()
+                .filter_map(|line| match bookmark_from_markdown_url(&line.data) {
+                    Some(b) => Some(entry(b.url, b.title, line.tags)),
+                    None => {
+                        let url = line.data.trim_start_matches(['-', ' ']).trim();
+                        let _ = Uri::from_str(url).ok()?;
+                        Some(entry(url.into(), None, line.tags))
+                    }
+                })
+                .collect();
+            Ok(bookmarks)
+        }
+    }
+
+    impl BookmarkSourceParser for ChromiumParser {
+        fn parse(
+            &self,
+            text: &str,
+            failed: &mut Vec<WithContext<String, String>>,
+        ) -> Result<Vec<ImportedBookmark>, Error> {
+            parse_chromium(text, failed)
+        }
+    }
+
+    /*
+    * heading                    :tag:tag:
+    ** TODO heading
+    - [[https://somelink][link title]]
+    - https://somelink
+    */
+    impl BookmarkSourceParser for OrgParser {
+        fn parse(
+            &self,
+            text: &str,
+            _failed: &mut Vec<WithContext<String, String>>,
+        ) -> Result<Vec<ImportedBookmark>, Error> {
+            // level and tags of the headings above the current line
+            let mut headings = Vec::<(usize, Vec<String>)>::new();
+            let mut bookmarks = vec![];
+            let mut in_block = false;
+            for line in text.lines() {
+                let trimmed = line.trim_start().to_lowercase();
+                if trimmed.starts_with("#+begin_src") || trimmed.starts_with("#+begin_example") {
+                    in_block = true;
+                } else if trimmed.starts_with("#+end_src") || trimmed.starts_with("#+end_example") {
+                    in_block = false;
+                }
+                // comments and #+keywords
+                if in_block || trimmed.starts_with('#') {
+                    continue;
+                }
+
+                let level = line.chars().take_while(|&c| c == '*').count();
+                if level > 0 && line[level..].starts_with(' ') {
+                    headings.retain(|(l, _)| *l < level);
+                    let (title, mut tags) = org_heading(&line[level..]);
+                    let links = links_in(title);
+                    // a heading that is a link is a bookmark, not a tag
+                    if links.is_empty() && !title.is_empty() {
+                        tags.insert(0, title.to_owned());
+                    }
+                    let parents = headings
+                        .iter()
+                        .flat_map(|(_, t)| t)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    bookmarks.extend(links.into_iter().map(|(u, t)| entry(u, t, parents.clone())));
+                    headings.push((level, tags));
+                    continue;
+                }
+
+                let tags = headings
+                    .iter()
+                    .flat_map(|(_, t)| t)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                bookmarks.extend(
+                    links_in(line)
+                        .into_iter()
+                        .map(|(u, t)| entry(u, t, tags.clone())),
+                );
+            }
+            Ok(bookmarks)
+        }
+    }
+
+    // all links of the note get the tags in the frontmatter, the #tags in the text and the
+    // notes it links to
+    impl BookmarkSourceParser for ObsidianParser {
+        fn parse(
+            &self,
+            text: &str,
+            _failed: &mut Vec<WithContext<String, String>>,
+        ) -> Result<Vec<ImportedBookmark>, Error> {
+            let (frontmatter, body) = split_frontmatter(text);
+            let mut tags = frontmatter.map(frontmatter_tags).unwrap_or_default();
+            let mut links = vec![];
+            let mut in_code = false;
+            for line in body.lines() {
+                if line.trim_start().starts_with("```") {
+                    in_code = !in_code;
+                    continue;
+                }
+                if in_code {
+                    continue;
+                }
+                for t in inline_tags(line).into_iter().chain(wiki_links(line)) {
+                    if !tags.contains(&t) {
+                        tags.push(t);
+                    }
+                }
+                links.extend(links_in(line));
+            }
+            let bookmarks = links
+                .into_iter()
+                .map(|(u, t)| entry(u, t, tags.clone()))
+                .collect();
+            Ok(bookmarks)
+        }
+    }
+
+    // https://somelink optional title
+    // lines starting with '#' are comments
+    impl BookmarkSourceParser for UrlListParser {
+        fn parse(
+            &self,
+            text: &str,
+            _failed: &mut Vec<WithContext<String, String>>,
+        ) -> Result<Vec<ImportedBookmark>, Error> {
+            let bookmarks = text
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| match l.split_once(char::is_whitespace) {
+                    Some((url, title)) => entry(url.into(), Some(title.trim().into()), vec![]),
+                    None => entry(l.into(), None, vec![]),
+                })
+                .collect();
+            Ok(bookmarks)
+        }
+    }
+
+    fn entry(url: String, title: Option<String>, tags: Vec<String>) -> ImportedBookmark {
+        ImportedBookmark {
+            bookmark: Tagged {
+                data: Bookmark {
+                    url,
+                    title: title.filter(|t| !t.is_empty()),
+                    description: None,
+                    source: None,
+                    link: None,
+                    archive: None,
+                    crawled: None,
+                    image: None,
+                    icon: None,
+                    site_name: None,
+                    canonical: None,
+                    source_tags: None,
+                },
+                tags,
+            },
+            ctime: None,
+            keywords: vec![],
+        }
+    }
+
+    // http(s) urls in the line. the title is taken from markdown [title](url) and org
+    // [[url][title]] links
+    fn links_in(line: &str) -> Vec<(String, Option<String>)> {
+        let mut links = vec![];
+        let mut from = 0;
+        while let Some(start) = find_url(&line[from..]).map(|s| s + from) {
+            let end = start + url_len(&line[start..]);
+            let (before, after) = (&line[..start], &line[end..]);
+            let title = if let Some(before) = before.strip_suffix("](") {
+                before.rfind('[').map(|i| &before[i + 1..])
+            } else if before.ends_with("[[") && after.starts_with("][") {
+                after[2..].find("]]").map(|i| &after[2..2 + i])
+            } else {
+                None
+            };
+            links.push((
+                line[start..end].to_owned(),
+                title.map(|t| t.trim().to_owned()),
+            ));
+            from = end.max(start + 1);
+        }
+        links
+    }
+
+    fn find_url(s: &str) -> Option<usize> {
+        [s.find("http://"), s.find("https://")]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    // urls end at whitespace, quotes and brackets. a ')' only ends it if it has no '(' in the
+    // url (wikipedia urls have them) and trailing punctuation is left out
+    fn url_len(s: &str) -> usize {
+        let mut depth = 0;
+        let mut end = s.len();
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' | '[' | ']' | '<' | '>' | '"' | '\'' | '`' => {
+                    end = i;
+                    break;
+                }
+                c if c.is_whitespace() => {
+                    end = i;
+                    break;
+                }
+                _ => (),
+            }
+        }
+        s[..end]
+            .trim_end_matches(['.', ',', ';', ':', '!', '?'])
+            .len()
+    }
+
+    // ' TODO [#A] title   :tag:tag:' -> ("title", ["tag", "tag"])
+    fn org_heading(heading: &str) -> (&str, Vec<String>) {
+        let mut title = heading.trim();
+        let mut tags = vec![];
+        if let Some((rest, last)) = title.rsplit_once(char::is_whitespace) {
+            if last.len() > 1 && last.starts_with(':') && last.ends_with(':') {
+                tags = last
+                    .split(':')
+                    .filter(|t| !t.is_empty())
+                    .map(String::from)
+                    .collect();
+                title = rest.trim_end();
+            }
+        }
+        for keyword in ["TODO ", "DONE "] {
+            title = title.strip_prefix(keyword).unwrap_or(title);
+        }
+        if title.starts_with("[#") {
+            title = title.split_once(']').map(|(_, t)| t).unwrap_or(title);
+        }
+        (title.trim(), tags)
+    }
+
+    // the yaml between the '---' lines at the start of the note
+    fn split_frontmatter(text: &str) -> (Option<&str>, &str) {
+        let Some(rest) = text
+            .strip_prefix("---")
+            .filter(|r| r.starts_with('\n') || r.starts_with("\r\n"))
+        else {
+            return (None, text);
+        };
+        match rest.find("\n---") {
+            Some(i) => {
+                let body = rest[i + 4..].split_once('\n').map(|(_, b)| b);
+                (Some(&rest[..i]), body.unwrap_or_default())
+            }
+            None => (None, text),
+        }
+    }
+
+    // tags: [a, b] / tags: a, b / tags: a b / a yaml list below tags:
+    fn frontmatter_tags(frontmatter: &str) -> Vec<String> {
+        let mut tags = vec![];
+        let mut in_list = false;
+        for line in frontmatter.lines() {
+            if in_list {
+                if let Some(t) = line.trim_start().strip_prefix('-') {
+                    tags.extend(split_tag(t));
+                    continue;
+                }
+                in_list = false;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            if !matches!(key.trim(), "tags" | "tag") {
+                continue;
+            }
+            let value = value.trim().trim_start_matches('[').trim_end_matches(']');
+            if value.is_empty() {
+                in_list = true;
+            }
+            tags.extend(value.split([',', ' ']).flat_map(split_tag));
+        }
+        tags
+    }
+
+    // #tag and #nested/tag. headings ('# title') and numbers ('#123') are not tags
+    fn inline_tags(line: &str) -> Vec<String> {
+        line.split_whitespace()
+            .filter_map(|w| w.strip_prefix('#'))
+            .filter_map(|t| {
+                t.split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '/')))
+                    .next()
+            })
+            .filter(|t| t.chars().any(|c| !c.is_numeric()))
+            .flat_map(split_tag)
+            .collect()
+    }
+
+    // links to other notes are used like tags: [[folder/rust#section|alias]] is the tag
+    // 'rust'. embeds (![[image.png]]) are not
+    fn wiki_links(line: &str) -> Vec<String> {
+        let mut tags = vec![];
+        let mut rest = line;
+        while let Some(start) = rest.find("[[") {
+            let embed = rest[..start].ends_with('!');
+            rest = &rest[start + 2..];
+            let Some(end) = rest.find("]]") else {
+                break;
+            };
+            let target = rest[..end].split(['|', '#']).next().unwrap_or_default();
+            let name = target.rsplit('/').next().unwrap_or_default().trim();
+            if !embed && !name.is_empty() && find_url(name).is_none() {
+                tags.push(name.to_owned());
+            }
+            rest = &rest[end + 2..];
+        }
+        tags
+    }
+
+    // nested tags become one tag per level, like nested folders
+    fn split_tag(tag: &str) -> Vec<String> {
+        tag.trim()
+            .trim_matches(['"', '\''])
+            .trim_start_matches('#')
+            .split('/')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{commit, test_config, test_db};
+
+    const SOURCE: &str = "\
+# lang + programming
+  - [Rust](https://www.rust-lang.org/)
+# search
+  - [tantivy](https://github.com/quickwit-oss/tantivy)
+  - https://docs.rs/tantivy
+";
+
+    fn sync(db: &AppDatabase, dir: &std::path::Path, source: Id) -> SourceRefreshReport {
+        let mut entries = parser(BookmarkSourceFormat::Markdown)
+            .parse(SOURCE, &mut vec![])
+            .unwrap();
+        for b in entries.iter_mut() {
+            b.bookmark.data.source = Some(source);
+            b.bookmark.tags.push("links".into());
+        }
+        let config = test_config(dir);
+        let (report, _added) = sync_source(db, &config, source, "links".into(), entries).unwrap();
+        commit(db);
+        report
+    }
+
+    #[tokio::test]
+    async fn unchanged_source_is_unchanged() {
+        let (dir, db) = test_db().await;
+        let source = db.new_id();
+
+        let report = sync(&db, dir.path(), source);
+        assert_eq!((report.added, report.changed, report.removed), (3, 0, 0));
+        let tags = source_bookmarks(&db, source).unwrap()[0]
+            .data
+            .data
+            .tags
+            .clone();
+
+        let report = sync(&db, dir.path(), source);
+        assert_eq!((report.added, report.changed, report.removed), (0, 0, 0));
+        assert!(report.failed.is_empty());
+        let bookmarks = source_bookmarks(&db, source).unwrap();
+        assert_eq!(bookmarks.len(), 3);
+        assert!(bookmarks.iter().any(|b| b.data.data.tags == tags));
+    }
+
+    #[test]
+    fn lines_that_are_not_links_are_left_out() {
+        let text = "# notes\n  - [ ] todo: read the docs\n  - [Rust](https://www.rust-lang.org/)\n";
+        let entries = parser(BookmarkSourceFormat::Markdown)
+            .parse(text, &mut vec![])
+            .unwrap();
+        let urls = entries
+            .iter()
+            .map(|b| b.bookmark.data.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["https://www.rust-lang.org/"]);
+    }
+
+    #[test]
+    fn vaults_are_read_note_by_note() {
+        let vault = tempfile::tempdir().unwrap();
+        let path = vault.path();
+        fs::create_dir_all(path.join(".obsidian")).unwrap();
+        fs::create_dir_all(path.join("lang")).unwrap();
+        fs::write(path.join(".obsidian/links.md"), "https://obsidian.md/").unwrap();
+        fs::write(
+            path.join("lang/rust.md"),
+            "#rust https://www.rust-lang.org/",
+        )
+        .unwrap();
+        fs::write(path.join("search.md"), "#search https://docs.rs/tantivy").unwrap();
+
+        assert_eq!(parsers::detect_format(path), BookmarkSourceFormat::Obsidian);
+        let mut entries = read_source(path, BookmarkSourceFormat::Obsidian, &mut vec![])
+            .unwrap()
+            .into_iter()
+            .map(|b| (b.bookmark.data.url, b.bookmark.tags))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(
+            entries,
+            [
+                ("https://docs.rs/tantivy".into(), vec!["search".into()]),
+                ("https://www.rust-lang.org/".into(), vec!["rust".into()]),
+            ] as [(String, Vec<String>); 2]
+        );
+
+        let mtime = source_mtime(path).unwrap();
+        fs::remove_file(path.join("lang/rust.md")).unwrap();
+        let dir = fs::File::open(path.join("lang")).unwrap();
+        dir.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(10))
+            .unwrap();
+        assert_ne!(source_mtime(path).unwrap(), mtime);
+    }
+}
@@ -0,0 +1,298 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use kolekk_types::objects::{
+    Fields, Id, LibraryStats, Meta, StorageStats, Tag, TypeFacet, WithContext,
+};
+use tantivy::{
+    collector::{Count, DocSetCollector},
+    query::{BooleanQuery, Occur, TermQuery},
+    schema::IndexRecordOption,
+    DocId, Term,
+};
+use tauri::State;
+
+use crate::{
+    bad_error::{Error, InferBadError},
+    config::AppConfig,
+    database::{AppDatabase, DbAble, FacetFrom, Searcher},
+};
+
+const DAY: u64 = 60 * 60 * 24;
+const TOP_TAGS: usize = 20;
+// walking the image dirs is slow, so disk usage is only recomputed after this
+const STORAGE_TTL: Duration = Duration::from_secs(60 * 5);
+// tag autocomplete runs on every keystroke. its ranking can use counts this old, so that
+// it doesn't count all the tags again after every commit
+const TAG_USAGE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct StatsCache {
+    storage: Mutex<Option<(Instant, StorageStats)>>,
+    // recomputed only when the searcher generation changes
+    usage: Mutex<Option<(u64, Instant, Arc<Usage>)>>,
+}
+
+struct Usage {
+    // sorted by most used first
+    tags: Vec<(Id, u64)>,
+    // number of objects per tag. aliases get the count of their main tag
+    tag_usage: Arc<HashMap<u64, u64>>,
+    per_day: Vec<WithContext<u64, u64>>,
+    per_month: Vec<WithContext<u64, u64>>,
+}
+
+#[tauri::command]
+pub async fn library_stats(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+    cache: State<'_, StatsCache>,
+) -> Result<LibraryStats, Error> {
+    let db = db.inner();
+    let searcher = db.get_searcher();
+
+    let objects = user_facets()
+        .into_iter()
+        .map(|f| {
+            let count = searcher.search(&facet_query(db, &f), &Count).infer_err()?;
+            Ok(WithContext {
+                data: f,
+                context: count as _,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let tags = objects
+        .iter()
+        .find(|o| matches!(o.data, TypeFacet::Tag))
+        .map(|o| o.context)
+        .unwrap_or(0);
+
+    let usage = cached_usage(db, &searcher, cache.inner(), Duration::ZERO)?;
+    let top_tags = usage
+        .tags
+        .iter()
+        .take(TOP_TAGS)
+        .filter_map(|&(id, count)| {
+            // tags that got deleted can still be on some objects
+            let mut doc = db.get_doc(id).ok()?;
+            let t: Meta<Tag, TypeFacet> = DbAble::take(db, &mut doc).ok()?;
+            Some(WithContext {
+                data: t,
+                context: count,
+            })
+        })
+        .collect();
+
+    let storage = {
+        let mut storage = cache.storage.lock().infer_err()?;
+        match storage.as_ref() {
+            Some((t, s)) if t.elapsed() < STORAGE_TTL => *s,
+            _ => {
+                let s = StorageStats {
+                    images: dir_size(&config.app_data_dir.join("images")),
+                    thumbnails: dir_size(&config.app_data_dir.join("thumbnails")),
+                    index: dir_size(&config.app_data_dir.join("database").join("tantivy")),
+                };
+                *storage = Some((Instant::now(), s));
+                s
+            }
+        }
+    };
+
+    Ok(LibraryStats {
+        objects,
+        tags,
+        top_tags,
+        added_per_day: usage.per_day.clone(),
+        added_per_month: usage.per_month.clone(),
+        storage,
+    })
+}
+
+pub fn tag_usage(db: &AppDatabase, cache: &StatsCache) -> Result<Arc<HashMap<u64, u64>>, Error> {
+    let usage = cached_usage(db, &db.get_searcher(), cache, TAG_USAGE_TTL)?;
+    Ok(usage.tag_usage.clone())
+}
+
+// usage younger than max_age is used even if the index changed since
+fn cached_usage(
+    db: &AppDatabase,
+    searcher: &Searcher,
+    cache: &StatsCache,
+    max_age: Duration,
+) -> Result<Arc<Usage>, Error> {
+    let generation = searcher.generation().generation_id();
+    let mut usage = cache.usage.lock().infer_err()?;
+    match usage.as_ref() {
+        Some((g, t, u)) if *g == generation || t.elapsed() < max_age => Ok(u.clone()),
+        _ => {
+            let u = Arc::new(compute_usage(db, searcher)?);
+            *usage = Some((generation, Instant::now(), u.clone()));
+            Ok(u)
+        }
+    }
+}
+
+fn compute_usage(db: &AppDatabase, searcher: &Searcher) -> Result<Usage, Error> {
+    let q = BooleanQuery::new(
+        user_facets()
+            .iter()
+            .map(|f| (Occur::Should, Box::new(facet_query(db, f)) as _))
+            .collect(),
+    );
+    let docs = searcher.search(&q, &DocSetCollector).infer_err()?;
+    let mut segments = HashMap::<u32, Vec<DocId>>::new();
+    for d in docs {
+        segments.entry(d.segment_ord).or_default().push(d.doc_id);
+    }
+
+    let mut tags = HashMap::<Id, u64>::new();
+    let mut per_day = BTreeMap::<u64, u64>::new();
+    let mut per_month = BTreeMap::<u64, u64>::new();
+    let mut vals = vec![];
+    for (ord, docs) in segments {
+        let reader = searcher.segment_reader(ord);
+        let ctime = reader
+            .fast_fields()
+            .u64(db.get_field(Fields::Ctime))
+            .infer_err()?;
+        let tag_reader = reader
+            .fast_fields()
+            .u64s(db.get_field(Fields::Tag))
+            .infer_err()?;
+        for doc in docs {
+            let t = ctime.get_val(doc);
+            *per_day.entry(t - t % DAY).or_default() += 1;
+            *per_month.entry(month_start(t)).or_default() += 1;
+
+            tag_reader.get_vals(doc, &mut vals);
+            for &t in vals.iter() {
+                *tags.entry(t as _).or_default() += 1;
+            }
+        }
+    }
+
+    let mut tag_usage = tags
+        .iter()
+        .map(|(&id, &count)| (id as u64, count))
+        .collect::<HashMap<_, _>>();
+    let tag_docs = searcher
+        .search(&facet_query(db, &TypeFacet::Tag), &DocSetCollector)
+        .infer_err()?;
+    for address in tag_docs {
+        let mut doc = searcher.doc(address).infer_err()?;
+        let t: Meta<Tag, TypeFacet> = DbAble::take(db, &mut doc)?;
+        if let Tag::Alias { alias_to, .. } = t.data {
+            let count = tags.get(&alias_to).copied().unwrap_or(0);
+            let _ = tag_usage.insert(t.id as _, count);
+        }
+    }
+
+    let mut tags = tags.into_iter().collect::<Vec<_>>();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let buckets = |m: BTreeMap<u64, u64>| {
+        m.into_iter()
+            .map(|(data, context)| WithContext { data, context })
+            .collect()
+    };
+    Ok(Usage {
+        tags,
+        tag_usage: Arc::new(tag_usage),
+        per_day: buckets(per_day),
+        per_month: buckets(per_month),
+    })
+}
+
+// facets of the objects that the user adds. Temp and Internal facets are left out
+pub fn user_facets() -> [TypeFacet; 8] {
+    [
+        TypeFacet::Image,
+        TypeFacet::Bookmark,
+        TypeFacet::BookmarkSource,
+        TypeFacet::Tag,
+        TypeFacet::Group,
+        TypeFacet::Content,
+        TypeFacet::Notes,
+        TypeFacet::SavedSearch,
+    ]
+}
+
+fn facet_query(db: &AppDatabase, facet: &TypeFacet) -> TermQuery {
+    TermQuery::new(
+        Term::from_facet(db.get_field(Fields::Type), &facet.facet()),
+        IndexRecordOption::Basic,
+    )
+}
+
+// first second of the month (utc) that contains this time
+fn month_start(secs: u64) -> u64 {
+    // - [civil_from_days](https://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let days = (secs / DAY) as i64;
+    let z = days + 719468;
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day_of_month = doy - (153 * mp + 2) / 5; // 0 based
+    (days - day_of_month) as u64 * DAY
+}
+
+pub fn dir_size(dir: &Path) -> u64 {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        add_or_search_tag,
+        tests::{add_object, commit, test_db, test_object},
+    };
+
+    #[tokio::test]
+    async fn every_tag_of_an_object_is_counted() {
+        let (_dir, db) = test_db().await;
+        add_object(&db, test_object(&db, "a", vec![1, 2], 0));
+        add_object(&db, test_object(&db, "b", vec![2], DAY));
+        commit(&db);
+
+        let usage = compute_usage(&db, &db.get_searcher()).unwrap();
+        assert_eq!(usage.tags, [(2, 2), (1, 1)]);
+        assert_eq!(usage.tag_usage.get(&1), Some(&1));
+        let per_day = usage
+            .per_day
+            .iter()
+            .map(|d| (d.data, d.context))
+            .collect::<Vec<_>>();
+        assert_eq!(per_day, [(0, 1), (DAY, 1)]);
+    }
+
+    #[tokio::test]
+    async fn tag_usage_is_not_counted_on_every_commit() {
+        let (_dir, db) = test_db().await;
+        let cache = StatsCache::default();
+        let usage = tag_usage(&db, &cache).unwrap();
+
+        let _tag = add_or_search_tag(&db, "rust".into()).unwrap();
+        commit(&db);
+        assert!(Arc::ptr_eq(&usage, &tag_usage(&db, &cache).unwrap()));
+
+        // library_stats always counts again after a commit
+        let searcher = db.get_searcher();
+        let counted = cached_usage(&db, &searcher, &cache, Duration::ZERO).unwrap();
+        assert!(!Arc::ptr_eq(&usage, &counted.tag_usage));
+    }
+}
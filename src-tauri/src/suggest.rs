@@ -0,0 +1,280 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// tags are suggested from
+// - tags of objects with similar text
+// - tags that often appear together with the tags that the object already has
+// - tag names that appear in the url domain/path or the file path of the object
+
+use std::collections::{HashMap, HashSet};
+
+use kolekk_types::objects::{Fields, Id, Meta, ObjectDraft, Tag, TypeFacet, WithContext};
+use serde_json::Value;
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, MoreLikeThisQuery, Occur, Query, TermQuery},
+    schema::IndexRecordOption,
+    Term,
+};
+use tauri::State;
+
+use crate::{
+    bad_error::{Error, InferBadError},
+    database::{AppDatabase, DbAble, FacetFrom, JsmlObject},
+    tag::_get_tags_from_ids,
+};
+
+const SIMILAR_OBJECTS: usize = 30;
+const COOCCURRENCE_OBJECTS: usize = 200;
+const NAME_MATCHES: usize = 50;
+
+const SIMILAR_WEIGHT: f32 = 1.0;
+const COOCCURRENCE_WEIGHT: f32 = 0.7;
+const NAME_MATCH_WEIGHT: f32 = 1.5;
+
+// parts of urls and file names that are never meant as tags
+const IGNORED_TOKENS: [&str; 14] = [
+    "http", "https", "www", "com", "org", "net", "html", "htm", "php", "jpg", "jpeg", "png", "gif",
+    "webp",
+];
+
+struct Target {
+    id: Option<Id>,
+    text: Vec<String>,
+    tags: Vec<Id>,
+    // urls and paths
+    locations: Vec<String>,
+}
+
+// either id or draft should be given. the suggestions are sorted by score, highest first
+#[tauri::command]
+pub async fn suggest_tags(
+    db: State<'_, AppDatabase>,
+    id: Option<Id>,
+    draft: Option<ObjectDraft>,
+    limit: usize,
+) -> Result<Vec<WithContext<Meta<Tag, TypeFacet>, f32>>, Error> {
+    let db = db.inner();
+    let target = match (id, draft) {
+        (Some(id), _) => target_from_object(db, id)?,
+        (None, Some(d)) => Target {
+            id: None,
+            text: d.title.into_iter().collect(),
+            tags: d.tags,
+            locations: d.url.into_iter().chain(d.path).collect(),
+        },
+        (None, None) => return Ok(vec![]),
+    };
+
+    let mut scores = HashMap::<Id, f32>::new();
+    similar_object_tags(db, &target, &mut scores)?;
+    cooccurring_tags(db, &target, &mut scores)?;
+    location_tags(db, &target, &mut scores)?;
+    for t in target.tags.iter() {
+        let _ = scores.remove(t);
+    }
+
+    let mut scores = scores.into_iter().collect::<Vec<_>>();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scores.truncate(limit);
+    let tags: Vec<Meta<Tag, TypeFacet>> =
+        _get_tags_from_ids(scores.iter().map(|s| s.0).collect(), db)?;
+    let tags = tags
+        .into_iter()
+        .filter_map(|t| {
+            let score = scores.iter().find(|s| s.0 == t.id)?.1;
+            Some(WithContext {
+                data: t,
+                context: score,
+            })
+        })
+        .collect();
+    Ok(tags)
+}
+
+fn target_from_object(db: &AppDatabase, id: Id) -> Result<Target, Error> {
+    let mut doc = db.get_doc(id)?;
+    let text = doc
+        .get_all(db.get_field(Fields::Text))
+        .filter_map(|v| v.as_text().map(String::from))
+        .collect();
+    let obj: JsmlObject = DbAble::take(db, &mut doc)?;
+
+    // Bookmark::url, Image::src and Image::path / BookmarkSource::path
+    let data = &obj.data.data;
+    let locations = [
+        data.get("url"),
+        data.get("src").and_then(|s| s.get("Url")),
+        data.get("src")
+            .and_then(|s| s.get("Path"))
+            .and_then(|p| p.get("path")),
+        data.get("path").and_then(|p| p.get("path")),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(Value::as_str)
+    .map(String::from)
+    .collect();
+
+    Ok(Target {
+        id: Some(id),
+        text,
+        tags: obj.data.tags,
+        locations,
+    })
+}
+
+fn similar_object_tags(
+    db: &AppDatabase,
+    target: &Target,
+    scores: &mut HashMap<Id, f32>,
+) -> Result<(), Error> {
+    if target.text.is_empty() {
+        return Ok(());
+    }
+    let mut q: Vec<(Occur, Box<dyn Query>)> = vec![(
+        Occur::Must,
+        Box::new(
+            MoreLikeThisQuery::builder()
+                .with_min_doc_frequency(1)
+                .with_min_term_frequency(1)
+                .with_min_word_length(2)
+                .with_document_fields(vec![(
+                    db.get_field(Fields::Text),
+                    target
+                        .text
+                        .iter()
+                        .map(|t| tantivy::schema::Value::Str(t.clone()))
+                        .collect(),
+                )]),
+        ),
+    )];
+    if let Some(id) = target.id {
+        q.push((
+            Occur::MustNot,
+            Box::new(TermQuery::new(
+                Term::from_field_u64(db.get_field(Fields::Id), id as _),
+                IndexRecordOption::Basic,
+            )),
+        ));
+    }
+
+    let searcher = db.get_searcher();
+    let docs = searcher
+        .search(&BooleanQuery::new(q), &TopDocs::with_limit(SIMILAR_OBJECTS))
+        .infer_err()?;
+    // scores are relative to the most similar object
+    let top = docs.first().map(|d| d.0).unwrap_or(1.0).max(f32::EPSILON);
+    for (score, address) in docs {
+        let doc = searcher.doc(address).infer_err()?;
+        for t in doc
+            .get_all(db.get_field(Fields::Tag))
+            .filter_map(|t| t.as_u64())
+        {
+            *scores.entry(t as _).or_default() += SIMILAR_WEIGHT * score / top;
+        }
+    }
+    Ok(())
+}
+
+fn cooccurring_tags(
+    db: &AppDatabase,
+    target: &Target,
+    scores: &mut HashMap<Id, f32>,
+) -> Result<(), Error> {
+    let searcher = db.get_searcher();
+    for &tag in target.tags.iter() {
+        let docs = searcher
+            .search(
+                &TermQuery::new(
+                    Term::from_field_u64(db.get_field(Fields::Tag), tag as _),
+                    IndexRecordOption::Basic,
+                ),
+                &TopDocs::with_limit(COOCCURRENCE_OBJECTS),
+            )
+            .infer_err()?;
+        let total = docs.len() as f32;
+        for (_score, address) in docs {
+            let doc = searcher.doc(address).infer_err()?;
+            for t in doc
+                .get_all(db.get_field(Fields::Tag))
+                .filter_map(|t| t.as_u64())
+                .filter(|&t| t != tag as u64)
+            {
+                // fraction of the objects with this tag that also have t
+                *scores.entry(t as _).or_default() += COOCCURRENCE_WEIGHT / total;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn location_tags(
+    db: &AppDatabase,
+    target: &Target,
+    scores: &mut HashMap<Id, f32>,
+) -> Result<(), Error> {
+    let tokens = target
+        .locations
+        .iter()
+        .map(|l| match reqwest::Url::parse(l) {
+            // query strings are mostly noise
+            Ok(url) if url.scheme() != "file" => {
+                format!("{} {}", url.host_str().unwrap_or_default(), url.path())
+            }
+            _ => l.clone(),
+        })
+        .flat_map(|l| db.tokenize(&l))
+        .filter(|t| t.chars().count() > 2 && !IGNORED_TOKENS.contains(&t.as_str()))
+        .collect::<HashSet<_>>();
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    let q = BooleanQuery::new(vec![
+        (
+            Occur::Must,
+            Box::new(TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &TypeFacet::Tag.facet()),
+                IndexRecordOption::Basic,
+            )) as _,
+        ),
+        (
+            Occur::Must,
+            Box::new(BooleanQuery::new(
+                tokens
+                    .iter()
+                    .map(|t| {
+                        (
+                            Occur::Should,
+                            Box::new(TermQuery::new(
+                                Term::from_field_text(db.get_field(Fields::Text), t),
+                                IndexRecordOption::Basic,
+                            )) as _,
+                        )
+                    })
+                    .collect(),
+            )),
+        ),
+    ]);
+    let searcher = db.get_searcher();
+    for (_score, address) in searcher
+        .search(&q, &TopDocs::with_limit(NAME_MATCHES))
+        .infer_err()?
+    {
+        let mut doc = searcher.doc(address).infer_err()?;
+        let tag: Meta<Tag, TypeFacet> = DbAble::take(db, &mut doc)?;
+        let (name, id) = match &tag.data {
+            Tag::Main { name, .. } => (name, tag.id),
+            Tag::Alias { name, alias_to } => (name, *alias_to),
+        };
+        // "one piece" should not be suggested just because the url has "piece" in it
+        let words = db.tokenize(name);
+        let matched = words.iter().filter(|w| tokens.contains(*w)).count();
+        if matched > 0 {
+            *scores.entry(id).or_default() +=
+                NAME_MATCH_WEIGHT * matched as f32 / words.len() as f32;
+        }
+    }
+    Ok(())
+}
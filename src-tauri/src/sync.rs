@@ -0,0 +1,763 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - every device writes the objects it changed as numbered changelog files into its own
+//   dir in the shared sync dir, and replays the changelogs of all the other devices.
+// - ids are only unique per device, so objects are referred to as "<device>/<id>" in
+//   changelogs and a table maps the ids of objects from other devices to local ids.
+// - the change with the greater Meta::last_update wins. ties go to the greater device id.
+//   deleted objects keep a tombstone with the time of the deletion, so older changes can't
+//   bring them back.
+// - image files are copied into the sync dir named by their checksum, so the same file
+//   never gets copied twice.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use kolekk_types::{
+    objects::{Fields, Id, SyncReport, TypeFacet},
+    utility::Path as KPath,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tantivy::{
+    collector::{DocSetCollector, TopDocs},
+    query::{BooleanQuery, Occur, TermQuery},
+    schema::IndexRecordOption,
+    Document, Term,
+};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    archive::{archive_on_add, delete_archive},
+    bad_error::{BadError, Error, InferBadError},
+    config::AppConfig,
+    database::{AppDatabase, AutoDbAble, DbAble, FacetFrom},
+    filesystem::get_path,
+    stats::user_facets,
+};
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+// objects that changed since the last sync. filled by the database
+#[derive(Default)]
+pub struct SyncLog {
+    enabled: AtomicBool,
+    updated: Mutex<HashSet<Id>>,
+    deleted: Mutex<HashMap<Id, u64>>,
+}
+
+impl SyncLog {
+    pub fn updated(&self, id: Id) {
+        if self.enabled.load(Ordering::Relaxed) {
+            let _ = self.updated.lock().unwrap().insert(id);
+        }
+    }
+
+    pub fn deleted(&self, id: Id, time: u64) {
+        if self.enabled.load(Ordering::Relaxed) {
+            let _ = self.deleted.lock().unwrap().insert(id, time);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct SyncConfig {
+    dir: Option<PathBuf>,
+    device: String,
+    next_seq: u64,
+    // last changelog applied from each device
+    applied: HashMap<String, u64>,
+    // "<device>/<id>" of objects from other devices -> local id
+    ids: HashMap<String, Id>,
+    // local id of deleted objects -> time of the deletion
+    #[serde(default)]
+    tombstones: HashMap<Id, u64>,
+}
+impl AutoDbAble for SyncConfig {}
+
+pub struct SyncState {
+    config: Mutex<SyncConfig>,
+    // local id -> "<device>/<id>" for objects from other devices
+    origins: Mutex<HashMap<Id, String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "change")]
+enum Change {
+    Upsert {
+        origin: String,
+        last_update: u64,
+        // the tantivy document as json with ids replaced by "<device>/<id>"
+        doc: Map<String, Value>,
+        // checksum of the image file in the sync dir
+        file: Option<String>,
+    },
+    Delete {
+        origin: String,
+        time: u64,
+    },
+}
+
+pub fn init_sync(app_handle: &AppHandle, db: &AppDatabase) -> Result<(), Error> {
+    let config = load_config(db)?;
+    db.sync_log
+        .enabled
+        .store(config.dir.is_some(), Ordering::Relaxed);
+    let origins = config.ids.iter().map(|(g, &l)| (l, g.clone())).collect();
+    app_handle.manage(SyncState {
+        config: Mutex::new(config),
+        origins: Mutex::new(origins),
+    });
+
+    let handle = app_handle.app_handle();
+    let _ = tokio::task::spawn(async move {
+        loop {
+            tokio::time::sleep(SYNC_INTERVAL).await;
+            let handle = handle.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                let db = handle.state::<AppDatabase>();
+                let state = handle.state::<SyncState>();
+                let conf = handle.state::<AppConfig>();
+                sync_once(db.inner(), state.inner(), conf.inner(), &handle)
+            })
+            .await
+            .infer_err()
+            .and_then(|r| r)
+            .dbg();
+        }
+    });
+    Ok(())
+}
+
+// all existing objects are written to the changelog the first time sync is enabled
+#[tauri::command]
+pub async fn enable_sync(
+    db: State<'_, AppDatabase>,
+    state: State<'_, SyncState>,
+    dir: PathBuf,
+) -> Result<(), Error> {
+    let db = db.inner();
+    let mut config = state.config.lock().infer_err()?;
+    if config.device.is_empty() {
+        config.device = uuid::Uuid::new_v4().hyphenated().to_string();
+    }
+    let first_time = config.next_seq == 0;
+    std::fs::create_dir_all(dir.join("devices").join(&config.device)).infer_err()?;
+    std::fs::create_dir_all(dir.join("files")).infer_err()?;
+    config.dir = Some(dir);
+    db.sync_log.enabled.store(true, Ordering::Relaxed);
+
+    if first_time {
+        let q = BooleanQuery::new(
+            user_facets()
+                .iter()
+                .map(|f| {
+                    (
+                        Occur::Should,
+                        Box::new(TermQuery::new(
+                            Term::from_facet(db.get_field(Fields::Type), &f.facet()),
+                            IndexRecordOption::Basic,
+                        )) as _,
+                    )
+                })
+                .collect(),
+        );
+        let searcher = db.get_searcher();
+        for address in searcher.search(&q, &DocSetCollector).infer_err()? {
+            let doc = searcher.doc(address).infer_err()?;
+            if let Some(id) = doc
+                .get_first(db.get_field(Fields::Id))
+                .and_then(|e| e.as_u64())
+            {
+                db.sync_log.updated(id as _);
+            }
+        }
+    }
+
+    save_config(db, &config)
+}
+
+#[tauri::command]
+pub async fn disable_sync(
+    db: State<'_, AppDatabase>,
+    state: State<'_, SyncState>,
+) -> Result<(), Error> {
+    let db = db.inner();
+    let mut config = state.config.lock().infer_err()?;
+    config.dir = None;
+    db.sync_log.enabled.store(false, Ordering::Relaxed);
+    db.sync_log.updated.lock().infer_err()?.clear();
+    db.sync_log.deleted.lock().infer_err()?.clear();
+    save_config(db, &config)
+}
+
+#[tauri::command]
+pub async fn sync_now(
+    db: State<'_, AppDatabase>,
+    state: State<'_, SyncState>,
+    config: State<'_, AppConfig>,
+    app: State<'_, AppHandle>,
+) -> Result<SyncReport, Error> {
+    sync_once(db.inner(), state.inner(), config.inner(), app.inner())
+}
+
+fn sync_once(
+    db: &AppDatabase,
+    state: &SyncState,
+    conf: &AppConfig,
+    app: &AppHandle,
+) -> Result<SyncReport, Error> {
+    let mut config = state.config.lock().infer_err()?;
+    let mut origins = state.origins.lock().infer_err()?;
+    let Some(dir) = config.dir.clone() else {
+        return Ok(SyncReport {
+            sent: 0,
+            received: 0,
+        });
+    };
+
+    let sent = write_changelog(db, conf, &dir, &mut config, &origins)?;
+
+    let mut received = 0;
+    let mut added = vec![];
+    let mut pending = HashMap::new();
+    let devices = std::fs::read_dir(dir.join("devices")).infer_err()?;
+    let mut writer = db.index_writer.write().infer_err()?;
+    for device in devices.filter_map(|e| e.ok()) {
+        let Some(name) = device.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if name == config.device {
+            continue;
+        }
+        let applied = config.applied.get(&name).copied();
+        for (seq, path) in list_changelogs(&device.path())? {
+            if applied.map(|a| seq <= a).unwrap_or(false) {
+                continue;
+            }
+            let changes: Vec<Change> =
+                serde_json::from_slice(&std::fs::read(&path).infer_err()?).infer_err()?;
+            for change in changes {
+                let mut ctx = ImportContext {
+                    db,
+                    conf,
+                    dir: &dir,
+                    device: &name,
+                    config: &mut *config,
+                    origins: &mut *origins,
+                    added: &mut added,
+                    pending: &mut pending,
+                };
+                if ctx.apply(&writer, change)? {
+                    received += 1;
+                }
+            }
+            let _ = config.applied.insert(name.clone(), seq);
+        }
+    }
+
+    let _opstamp = writer.delete_term(Term::from_facet(db.get_field(Fields::Type), &sync_facet()));
+    let _opstamp = writer.add_document(config_doc(db, &config)?).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    archive_on_add(app, added)?;
+
+    Ok(SyncReport { sent, received })
+}
+
+fn write_changelog(
+    db: &AppDatabase,
+    conf: &AppConfig,
+    dir: &Path,
+    config: &mut SyncConfig,
+    origins: &HashMap<Id, String>,
+) -> Result<usize, Error> {
+    let updated = std::mem::take(&mut *db.sync_log.updated.lock().infer_err()?);
+    let deleted = std::mem::take(&mut *db.sync_log.deleted.lock().infer_err()?);
+    if updated.is_empty() && deleted.is_empty() {
+        return Ok(0);
+    }
+    db.index_reader.reload().infer_err()?;
+
+    let global = |id: Id| {
+        origins
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| format!("{}/{}", config.device, id))
+    };
+    let schema = db.schema();
+    let mut changes = vec![];
+    let mut uncommitted = vec![];
+    for &id in updated.iter().filter(|id| !deleted.contains_key(id)) {
+        let Ok(doc) = db.get_doc(id) else {
+            // deleted objects are tombstoned. the rest are not committed yet, so they are
+            // written with a later sync
+            if !config.tombstones.contains_key(&id) {
+                uncommitted.push(id);
+            }
+            continue;
+        };
+        let mut json: Map<String, Value> =
+            serde_json::from_str(&schema.to_json(&doc)).infer_err()?;
+        let Some(facet) = json
+            .get(&*Fields::Type)
+            .and_then(|f| f.get(0))
+            .and_then(|f| f.as_str())
+            .and_then(|f| TypeFacet::try_from(f).ok())
+        else {
+            continue;
+        };
+        if matches!(facet, TypeFacet::Temp(_) | TypeFacet::Internal(_)) {
+            continue;
+        }
+        let last_update = doc
+            .get_first(db.get_field(Fields::Mtime))
+            .and_then(|e| e.as_u64())
+            .unwrap_or(0);
+
+        let _ = json.remove(&*Fields::Id);
+        for v in doc_refs(&facet, &mut json) {
+            if let Some(id) = v.as_u64() {
+                *v = Value::String(global(id as _));
+            }
+        }
+        let file = match facet {
+            TypeFacet::Image => export_file(conf, dir, &json)?,
+            _ => None,
+        };
+
+        changes.push(Change::Upsert {
+            origin: global(id),
+            last_update,
+            doc: json,
+            file,
+        });
+    }
+    db.sync_log.updated.lock().infer_err()?.extend(uncommitted);
+    for (id, time) in deleted {
+        let _ = config.tombstones.insert(id, time);
+        changes.push(Change::Delete {
+            origin: global(id),
+            time,
+        });
+    }
+
+    if changes.is_empty() {
+        return Ok(0);
+    }
+
+    let device_dir = dir.join("devices").join(&config.device);
+    std::fs::create_dir_all(&device_dir).infer_err()?;
+    let tmp = device_dir.join(format!(".{}.tmp", config.next_seq));
+    std::fs::write(&tmp, serde_json::to_vec(&changes).infer_err()?).infer_err()?;
+    std::fs::rename(&tmp, device_dir.join(format!("{}.json", config.next_seq))).infer_err()?;
+    config.next_seq += 1;
+
+    Ok(changes.len())
+}
+
+struct ImportContext<'a> {
+    db: &'a AppDatabase,
+    conf: &'a AppConfig,
+    dir: &'a Path,
+    // the device that wrote the changelog
+    device: &'a str,
+    config: &'a mut SyncConfig,
+    origins: &'a mut HashMap<Id, String>,
+    // bookmarks that are new on this device. archives are not synced
+    added: &'a mut Vec<Id>,
+    // id -> time and device of the last change applied to it in this sync. those changes
+    // are not committed yet, so wins can't see them in the index
+    pending: &'a mut HashMap<Id, (u64, String)>,
+}
+
+impl ImportContext<'_> {
+    // returns if the change was applied
+    fn apply(&mut self, writer: &tantivy::IndexWriter, change: Change) -> Result<bool, Error> {
+        match change {
+            Change::Upsert {
+                origin,
+                last_update,
+                mut doc,
+                file,
+            } => {
+                let id = self.local_id(&origin)?;
+                if !self.wins(id, last_update) {
+                    return Ok(false);
+                }
+
+                let Some(facet) = doc
+                    .get(&*Fields::Type)
+                    .and_then(|f| f.get(0))
+                    .and_then(|f| f.as_str())
+                    .and_then(|f| TypeFacet::try_from(f).ok())
+                else {
+                    return None.bad_err("changelog object has no type");
+                };
+                for v in doc_refs(&facet, &mut doc) {
+                    if let Some(g) = v.as_str().map(String::from) {
+                        *v = Value::from(self.local_id(&g)?);
+                    }
+                }
+                if let Some(chksum) = file {
+                    import_file(self.conf, self.dir, &chksum, &doc)?;
+                }
+                let _ = doc.insert(Fields::Id.to_string(), Value::from(vec![id]));
+
+                let mut doc = self
+                    .db
+                    .schema()
+                    .parse_document(&Value::Object(doc).to_string())
+                    .infer_err()?;
+                // only the stored fields are in the changelog
+                self.db.derive_fields(&mut doc);
+                if matches!(facet, TypeFacet::Bookmark)
+                    && self.db.get_doc(id).is_err()
+                    && !self.added.contains(&id)
+                {
+                    self.added.push(id);
+                }
+                let _opstamp = writer
+                    .delete_term(Term::from_field_u64(self.db.get_field(Fields::Id), id as _));
+                let _opstamp = writer.add_document(doc).infer_err()?;
+                let _ = self.config.tombstones.remove(&id);
+                let _ = self
+                    .pending
+                    .insert(id, (last_update, self.device.to_owned()));
+                Ok(true)
+            }
+            Change::Delete { origin, time } => {
+                let id = self.local_id(&origin)?;
+                if !self.wins(id, time) {
+                    return Ok(false);
+                }
+                let _opstamp = writer
+                    .delete_term(Term::from_field_u64(self.db.get_field(Fields::Id), id as _));
+                let _ = delete_archive(self.conf, id).dbg();
+                // the object might not have arrived here yet
+                let _ = self.config.tombstones.insert(id, time);
+                let _ = self.pending.insert(id, (time, self.device.to_owned()));
+                Ok(true)
+            }
+        }
+    }
+
+    fn wins(&self, id: Id, last_update: u64) -> bool {
+        if let Some((t, device)) = self.pending.get(&id) {
+            return match last_update.cmp(t) {
+                std::cmp::Ordering::Equal => self.device > device.as_str(),
+                o => o.is_gt(),
+            };
+        }
+        let local = self
+            .db
+            .get_doc(id)
+            .ok()
+            .and_then(|d| d.get_first(self.db.get_field(Fields::Mtime))?.as_u64())
+            .or_else(|| self.config.tombstones.get(&id).copied());
+        match local {
+            None => true,
+            Some(t) if t == last_update => self.device > self.config.device.as_str(),
+            Some(t) => t < last_update,
+        }
+    }
+
+    // objects that are referenced before they arrive get an id reserved for them
+    fn local_id(&mut self, global: &str) -> Result<Id, Error> {
+        let (device, id) = global.rsplit_once('/').bad_err("bad global id")?;
+        if device == self.config.device {
+            return id.parse().infer_err();
+        }
+        if let Some(&id) = self.config.ids.get(global) {
+            return Ok(id);
+        }
+        let id = self.db.new_id();
+        let _ = self.config.ids.insert(global.to_owned(), id);
+        let _ = self.origins.insert(id, global.to_owned());
+        Ok(id)
+    }
+}
+
+// values in the document that hold ids of other objects
+fn doc_refs<'a>(facet: &TypeFacet, doc: &'a mut Map<String, Value>) -> Vec<&'a mut Value> {
+    let json_keys: &[&str] = match facet {
+        TypeFacet::Group => &["items", "main"],
+        TypeFacet::Bookmark => &["source"],
+        TypeFacet::Tag => &["alias_to"],
+        TypeFacet::SavedSearch => &["filters"],
+        _ => &[],
+    };
+    let mut refs = vec![];
+    for (k, v) in doc.iter_mut() {
+        if *k == *Fields::Tag || *k == *Fields::SourceId {
+            refs.extend(v.as_array_mut().into_iter().flatten());
+        } else if *k == *Fields::Json {
+            let objects = v.as_array_mut().into_iter().flatten();
+            for (k, v) in objects.filter_map(|o| o.as_object_mut()).flatten() {
+                if !json_keys.contains(&k.as_str()) {
+                    continue;
+                }
+                let v = if k == "filters" {
+                    v.get_mut("tags")
+                } else {
+                    Some(v)
+                };
+                match v {
+                    Some(Value::Array(a)) => refs.extend(a.iter_mut()),
+                    Some(v) if !v.is_null() => refs.push(v),
+                    _ => {}
+                }
+            }
+        }
+    }
+    refs
+}
+
+fn image_file(conf: &AppConfig, doc: &Map<String, Value>) -> Option<(PathBuf, String)> {
+    let json = doc.get(&*Fields::Json)?.get(0)?;
+    let path: KPath = serde_json::from_value(json.get("path")?.clone()).ok()?;
+    let chksum = json
+        .get("chksum")?
+        .as_array()?
+        .iter()
+        .map(|b| b.as_u64().map(|b| format!("{:02x}", b)))
+        .collect::<Option<String>>()?;
+    Some((get_path(&path, conf), chksum))
+}
+
+fn export_file(
+    conf: &AppConfig,
+    dir: &Path,
+    doc: &Map<String, Value>,
+) -> Result<Option<String>, Error> {
+    let Some((path, chksum)) = image_file(conf, doc) else {
+        return Ok(None);
+    };
+    let dest = dir.join("files").join(&chksum);
+    if !dest.exists() && path.exists() {
+        let tmp = dest.with_extension("tmp");
+        let _ = std::fs::copy(&path, &tmp).infer_err()?;
+        std::fs::rename(&tmp, &dest).infer_err()?;
+    }
+    Ok(Some(chksum))
+}
+
+fn import_file(
+    conf: &AppConfig,
+    dir: &Path,
+    chksum: &str,
+    doc: &Map<String, Value>,
+) -> Result<(), Error> {
+    let Some((path, _)) = image_file(conf, doc) else {
+        return Ok(());
+    };
+    let src = dir.join("files").join(chksum);
+    // the file might not have been synced to this machine yet
+    if path.exists() || !src.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).infer_err()?;
+    }
+    let _ = std::fs::copy(&src, &path).infer_err()?;
+    Ok(())
+}
+
+fn list_changelogs(dir: &Path) -> Result<Vec<(u64, PathBuf)>, Error> {
+    let mut logs = std::fs::read_dir(dir)
+        .infer_err()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let seq = e.path().file_stem()?.to_str()?.parse().ok()?;
+            Some((seq, e.path()))
+        })
+        .collect::<Vec<_>>();
+    logs.sort_unstable_by_key(|(seq, _)| *seq);
+    Ok(logs)
+}
+
+fn sync_facet() -> tantivy::schema::Facet {
+    TypeFacet::Internal("/app_data/sync".into()).facet()
+}
+
+fn load_config(db: &AppDatabase) -> Result<SyncConfig, Error> {
+    let searcher = db.get_searcher();
+    let config = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &sync_facet()),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(1),
+        )
+        .infer_err()?
+        .first()
+        .and_then(|&(_, add)| searcher.doc(add).ok())
+        .and_then(|mut doc| DbAble::take(db, &mut doc).ok())
+        .unwrap_or_default();
+    Ok(config)
+}
+
+fn save_config(db: &AppDatabase, config: &SyncConfig) -> Result<(), Error> {
+    let doc = config_doc(db, config)?;
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_facet(db.get_field(Fields::Type), &sync_facet()));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+fn config_doc(db: &AppDatabase, config: &SyncConfig) -> Result<Document, Error> {
+    let mut doc = Document::new();
+    doc.add_facet(db.get_field(Fields::Type), sync_facet());
+    config.clone().add(db, &mut doc)?;
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::collector::Count;
+
+    use crate::database::tests::{
+        add_object, commit, object_doc, test_config, test_db, test_object,
+    };
+
+    // the document as another device would write it to its changelog
+    fn remote_doc(db: &AppDatabase, text: &str, last_update: u64) -> Map<String, Value> {
+        let mut obj = test_object(db, text, vec![], 1);
+        obj.last_update = last_update;
+        let doc = object_doc(db, obj);
+        let mut json: Map<String, Value> =
+            serde_json::from_str(&db.schema().to_json(&doc)).unwrap();
+        let _ = json.remove(&*Fields::Id);
+        json
+    }
+
+    fn upsert(db: &AppDatabase, last_update: u64) -> Change {
+        Change::Upsert {
+            origin: "a/1".into(),
+            last_update,
+            doc: remote_doc(db, "rust programming", last_update),
+            file: None,
+        }
+    }
+
+    fn apply(
+        db: &AppDatabase,
+        dir: &Path,
+        config: &mut SyncConfig,
+        origins: &mut HashMap<Id, String>,
+        change: Change,
+    ) -> bool {
+        let mut ctx = ImportContext {
+            db,
+            conf: &test_config(dir),
+            dir,
+            device: "a",
+            config,
+            origins,
+            added: &mut vec![],
+            pending: &mut HashMap::new(),
+        };
+        let applied = ctx.apply(&db.index_writer.read().unwrap(), change).unwrap();
+        commit(db);
+        applied
+    }
+
+    #[tokio::test]
+    async fn deletions_are_not_undone() {
+        let (dir, db) = test_db().await;
+        let dir = dir.path();
+        let mut config = SyncConfig {
+            device: "b".into(),
+            ..Default::default()
+        };
+        let mut origins = HashMap::new();
+
+        assert!(apply(&db, dir, &mut config, &mut origins, upsert(&db, 10)));
+        let id = config.ids["a/1"];
+        // the prefix field is not stored, so it has to be rebuilt
+        let prefix = TermQuery::new(
+            Term::from_field_text(db.get_field(Fields::Prefix), "progr"),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(db.get_searcher().search(&prefix, &Count).unwrap(), 1);
+
+        let delete = Change::Delete {
+            origin: "a/1".into(),
+            time: 20,
+        };
+        assert!(apply(&db, dir, &mut config, &mut origins, delete));
+        assert!(db.get_doc(id).is_err());
+        assert!(!apply(&db, dir, &mut config, &mut origins, upsert(&db, 15)));
+        assert!(db.get_doc(id).is_err());
+        assert!(apply(&db, dir, &mut config, &mut origins, upsert(&db, 30)));
+        assert!(db.get_doc(id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn newest_change_in_a_sync_wins() {
+        let (dir, db) = test_db().await;
+        let dir = dir.path();
+        let conf = test_config(dir);
+        let mut config = SyncConfig {
+            device: "b".into(),
+            ..Default::default()
+        };
+        let mut origins = HashMap::new();
+        let mut pending = HashMap::new();
+
+        let writer = db.index_writer.read().unwrap();
+        for (device, last_update, applied) in [("a", 30, true), ("c", 20, false)] {
+            let mut ctx = ImportContext {
+                db: &db,
+                conf: &conf,
+                dir,
+                device,
+                config: &mut config,
+                origins: &mut origins,
+                added: &mut vec![],
+                pending: &mut pending,
+            };
+            assert_eq!(
+                ctx.apply(&writer, upsert(&db, last_update)).unwrap(),
+                applied
+            );
+        }
+        drop(writer);
+        commit(&db);
+
+        let doc = db.get_doc(config.ids["a/1"]).unwrap();
+        let mtime = doc.get_first(db.get_field(Fields::Mtime)).unwrap().as_u64();
+        assert_eq!(mtime, Some(30));
+    }
+
+    #[tokio::test]
+    async fn uncommitted_objects_are_synced_later() {
+        let (dir, db) = test_db().await;
+        let dir = dir.path();
+        let conf = test_config(dir);
+        let mut config = SyncConfig {
+            device: "b".into(),
+            ..Default::default()
+        };
+        db.sync_log.enabled.store(true, Ordering::Relaxed);
+
+        add_object(&db, test_object(&db, "rust", vec![], 1));
+        let written = write_changelog(&db, &conf, dir, &mut config, &HashMap::new()).unwrap();
+        assert_eq!(written, 0);
+
+        commit(&db);
+        let written = write_changelog(&db, &conf, dir, &mut config, &HashMap::new()).unwrap();
+        assert_eq!(written, 1);
+        assert_eq!(config.next_seq, 1);
+    }
+}
@@ -1,15 +1,27 @@
 #[allow(unused_imports)]
 use crate::{dbg, debug, error};
 
+use std::collections::HashSet;
+
 use kolekk_types::objects::{Fields, Id, Indexed, Meta, SearchableEntry, Tag, TypeFacet};
-use tantivy::{collector::TopDocs, query::{BooleanQuery, Occur, TermQuery}, schema::IndexRecordOption, Document, Term};
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, ConstScoreQuery, Occur, Query, TermQuery},
+    schema::IndexRecordOption,
+    Document, Term,
+};
 use tauri::State;
 
 use crate::{
     bad_error::{BadError, Error, InferBadError, Inspectable},
     database::{AppDatabase, DbAble, FacetFrom, TagSearchScoreTweaker},
+    stats::{tag_usage, StatsCache},
+    tokenizer::MAX_PREFIX_LEN,
 };
 
+// aliases pointing to aliases are followed only this deep
+const MAX_ALIAS_DEPTH: usize = 4;
+
 #[tauri::command]
 pub async fn search_tags(
     db: State<'_, AppDatabase>,
@@ -27,6 +39,85 @@ pub async fn search_tags(
     )
 }
 
+// matches the start of words in tag names and aliases, for calling on every keystroke.
+// aliases are replaced by their main tag. ranked by usage and then last_interaction
+#[tauri::command]
+pub async fn autocomplete_tags(
+    db: State<'_, AppDatabase>,
+    cache: State<'_, StatsCache>,
+    query: String,
+    limit: usize,
+) -> Result<Vec<Meta<Tag, TypeFacet>>, Error> {
+    let db = db.inner();
+    let const_term = |field: Fields, text: &str| -> Box<dyn Query> {
+        Box::new(ConstScoreQuery::new(
+            Box::new(TermQuery::new(
+                Term::from_field_text(db.get_field(field), text),
+                IndexRecordOption::Basic,
+            )),
+            1.0,
+        ))
+    };
+
+    let mut q: Vec<(Occur, Box<dyn Query>)> = vec![(
+        Occur::Must,
+        Box::new(ConstScoreQuery::new(
+            Box::new(TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &TypeFacet::Tag.facet()),
+                IndexRecordOption::Basic,
+            )),
+            1.0,
+        )),
+    )];
+    for t in db.tokenize(&query) {
+        // longer prefixes are not indexed
+        let prefix = t
+            .char_indices()
+            .nth(MAX_PREFIX_LEN)
+            .map(|(i, _)| &t[..i])
+            .unwrap_or(&t);
+        q.push((Occur::Must, const_term(Fields::Prefix, prefix)));
+        // words that are typed out completely rank first
+        q.push((Occur::Should, const_term(Fields::Text, &t)));
+    }
+
+    // usage only changes the order, so tags are still completed if it can't be counted
+    let usage = tag_usage(db, cache.inner()).dbg().unwrap_or_default();
+    let tweaker = TagSearchScoreTweaker::new(db)?.with_usage(usage);
+    let searcher = db.get_searcher();
+    let mut seen = HashSet::new();
+    let mut tags = vec![];
+    // some of the results may resolve to the same main tag
+    for (_score, address) in searcher
+        .search(
+            &BooleanQuery::new(q),
+            &TopDocs::with_limit(limit * 2).tweak_score(tweaker),
+        )
+        .infer_err()?
+    {
+        let mut doc = searcher.doc(address).infer_err()?;
+        let mut tag: Meta<Tag, TypeFacet> = DbAble::take(db, &mut doc)?;
+        for _ in 0..MAX_ALIAS_DEPTH {
+            match tag.data {
+                Tag::Alias { alias_to, .. } => {
+                    match _get_tags_from_ids(vec![alias_to], db)?.pop() {
+                        Some(t) => tag = t,
+                        None => break,
+                    }
+                }
+                Tag::Main { .. } => break,
+            }
+        }
+        if matches!(tag.data, Tag::Main { .. }) && seen.insert(tag.id) {
+            tags.push(tag);
+        }
+        if tags.len() >= limit {
+            break;
+        }
+    }
+    Ok(tags)
+}
+
 #[tauri::command]
 pub async fn save_new_tag(db: State<'_, AppDatabase>, tag: Tag) -> Result<Id, Error> {
     let mut doc = Document::new();
@@ -37,14 +128,7 @@ pub async fn save_new_tag(db: State<'_, AppDatabase>, tag: Tag) -> Result<Id, Er
         id,
         facet: TypeFacet::Tag,
         data: SearchableEntry {
-            searchable: vec![Indexed {
-                field: Fields::Text,
-                data: match &tag {
-                    Tag::Main { name } | Tag::Alias { name, .. } => {
-                        serde_json::Value::String(name.to_string())
-                    }
-                },
-            }],
+            searchable: tag_searchables(&tag),
             data: tag,
         },
         ctime,
@@ -58,6 +142,64 @@ pub async fn save_new_tag(db: State<'_, AppDatabase>, tag: Tag) -> Result<Id, Er
     Ok(id)
 }
 
+// None clears the field
+#[tauri::command]
+pub async fn update_tag_metadata(
+    db: State<'_, AppDatabase>,
+    id: Id,
+    description: Option<String>,
+    color: Option<String>,
+    icon: Option<String>,
+    cover: Option<Id>,
+) -> Result<(), Error> {
+    let db = db.inner();
+    let color = color.map(|c| normalize_color(&c)).transpose()?;
+    if let Some(c) = cover {
+        let _ = db.get_doc_address(c)?;
+    }
+
+    let mut doc = db.get_doc(id)?;
+    let mut tag: Meta<SearchableEntry<Tag>, TypeFacet> = DbAble::take(db, &mut doc)?;
+    let name = match &tag.data.data {
+        Tag::Main { name, .. } => name.clone(),
+        Tag::Alias { .. } => return None.bad_err("alias tags can't have metadata"),
+    };
+    tag.data.data = Tag::Main {
+        name,
+        description: description.filter(|d| !d.trim().is_empty()),
+        color,
+        icon: icon.filter(|i| !i.trim().is_empty()),
+        cover,
+    };
+    tag.data.searchable = tag_searchables(&tag.data.data);
+    tag.last_update = db.now_time()?;
+
+    let mut doc = Document::new();
+    tag.add(db, &mut doc)?;
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+// the name is searchable. the description is indexed in Fields::Body so that looking up
+// tags by name never matches their description
+pub fn tag_searchables(tag: &Tag) -> Vec<Indexed> {
+    let indexed = |field: Fields, s: &str| Indexed {
+        field,
+        data: serde_json::Value::String(s.to_string()),
+    };
+    match tag {
+        Tag::Main {
+            name, description, ..
+        } => std::iter::once(indexed(Fields::Text, name))
+            .chain(description.iter().map(|d| indexed(Fields::Body, d)))
+            .collect(),
+        Tag::Alias { name, .. } => vec![indexed(Fields::Text, name)],
+    }
+}
+
 #[tauri::command]
 pub async fn get_tags_from_ids(
     ids: Vec<u32>,
@@ -101,3 +243,50 @@ pub fn _get_tags_from_ids<T: DbAble + std::fmt::Debug>(
         })
         .collect()
 }
+
+// hex colors, with or without the '#'. they are stored with it
+fn normalize_color(c: &str) -> Result<String, Error> {
+    let hex = c.strip_prefix('#').unwrap_or(c);
+    if !matches!(hex.len(), 3 | 6 | 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None.bad_err("bad color");
+    }
+    Ok(format!("#{}", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_are_not_names() {
+        let tag = Tag::Main {
+            name: "rust".into(),
+            description: Some("the programming language".into()),
+            color: None,
+            icon: None,
+            cover: None,
+        };
+        let fields = tag_searchables(&tag)
+            .into_iter()
+            .map(|i| (i.field, i.data))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                (Fields::Text, serde_json::Value::from("rust")),
+                (
+                    Fields::Body,
+                    serde_json::Value::from("the programming language")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn colors_are_stored_with_a_hash() {
+        assert_eq!(normalize_color("#a0b1c2").unwrap(), "#a0b1c2");
+        assert_eq!(normalize_color("fff").unwrap(), "#fff");
+        assert!(normalize_color("#ggg").is_err());
+        assert!(normalize_color("ffff").is_err());
+    }
+}
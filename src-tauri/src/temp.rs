@@ -0,0 +1,306 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - Temp facets ("/temp/...") hold data the user can see but has not decided to keep.
+//   facets created with new_temp_facet expire and get deleted by the sweeper, unless
+//   they are promoted into a permanent facet first. facets with fixed names (like the
+//   /temp/tachi/... caches of the frontend) are managed by whoever uses them and never
+//   expire.
+// - Internal facets ("/app_data/...", "/cache/...") hold app state. they never expire and
+//   can't be touched from the frontend.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use kolekk_types::objects::{Fields, Meta, SearchableEntry, Taggable, TypeFacet};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tantivy::{
+    collector::{DocSetCollector, TopDocs},
+    query::TermQuery,
+    schema::{Facet, IndexRecordOption},
+    Document, Term,
+};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    bad_error::{BadError, Error, InferBadError},
+    database::{AppDatabase, AutoDbAble, DbAble, FacetFrom},
+};
+
+type Object = Meta<Taggable<SearchableEntry<Map<String, Value>>>, Facet>;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 10);
+const DEFAULT_TTL: u64 = 60 * 60 * 24;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct TempFacets {
+    // facet -> unix time after which it gets deleted
+    expiry: HashMap<String, u64>,
+    // if the facets that existed before expiry got the default ttl
+    #[serde(default)]
+    existing_expire: bool,
+}
+
+impl AutoDbAble for TempFacets {}
+
+pub struct TempFacetState(Mutex<TempFacets>);
+
+pub fn init_temp_facets(app_handle: &AppHandle, db: &AppDatabase) -> Result<(), Error> {
+    let mut facets = load_expiry(db)?;
+    if !facets.existing_expire {
+        expire_existing(db, &mut facets)?;
+    }
+    app_handle.manage(TempFacetState(Mutex::new(facets)));
+
+    let handle = app_handle.app_handle();
+    let _ = tokio::task::spawn(async move {
+        loop {
+            let handle = handle.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                let db = handle.state::<AppDatabase>();
+                let state = handle.state::<TempFacetState>();
+                sweep(db.inner(), state.inner())
+            })
+            .await
+            .infer_err()
+            .and_then(|r| r)
+            .dbg();
+            tokio::time::sleep(SWEEP_INTERVAL).await;
+        }
+    });
+    Ok(())
+}
+
+// ttl in seconds. None uses the default ttl
+#[tauri::command]
+pub async fn new_temp_facet(
+    db: State<'_, AppDatabase>,
+    state: State<'_, TempFacetState>,
+    ttl: Option<u64>,
+) -> Result<String, Error> {
+    let id = uuid::Uuid::new_v4();
+    let facet = format!("/temp/{}", id.hyphenated());
+    set_expiry(
+        db.inner(),
+        state.inner(),
+        &facet,
+        Some(ttl.unwrap_or(DEFAULT_TTL)),
+    )?;
+    Ok(facet)
+}
+
+// ttl in seconds. None makes the facet never expire
+#[tauri::command]
+pub async fn set_temp_facet_ttl(
+    db: State<'_, AppDatabase>,
+    state: State<'_, TempFacetState>,
+    facet: TypeFacet,
+    ttl: Option<u64>,
+) -> Result<(), Error> {
+    match facet {
+        TypeFacet::Temp(facet) => set_expiry(db.inner(), state.inner(), &facet, ttl),
+        _ => None.bad_err("only temp facets can expire"),
+    }
+}
+
+// moves all objects of a temp facet into the given facet. returns the number of objects moved
+#[tauri::command]
+pub async fn promote_temp_facet(
+    db: State<'_, AppDatabase>,
+    state: State<'_, TempFacetState>,
+    from: TypeFacet,
+    to: TypeFacet,
+) -> Result<usize, Error> {
+    let db = db.inner();
+    if !matches!(from, TypeFacet::Temp(_)) {
+        return None.bad_err("only temp facets can be promoted");
+    }
+    if matches!(to, TypeFacet::Temp(_) | TypeFacet::Internal(_)) {
+        return None.bad_err("temp facets can only be promoted into a permanent facet");
+    }
+
+    let searcher = db.get_searcher();
+    let docs = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &from.facet()),
+                IndexRecordOption::Basic,
+            ),
+            &DocSetCollector,
+        )
+        .infer_err()?;
+    let time = db.now_time()?;
+    let to = to.facet();
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    for &address in docs.iter() {
+        let mut doc = searcher.doc(address).infer_err()?;
+        let mut obj: Object = DbAble::take(db, &mut doc)?;
+        obj.facet = to.clone();
+        obj.last_update = time;
+        let id = obj.id;
+        let mut doc = Document::new();
+        obj.add(db, &mut doc)?;
+        let _opstamp = writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+        let _opstamp = writer.add_document(doc).infer_err()?;
+    }
+    let _opstamp = writer.commit().infer_err()?;
+    drop(writer);
+
+    if let TypeFacet::Temp(facet) = from {
+        set_expiry(db, state.inner(), &facet, None)?;
+    }
+    Ok(docs.len())
+}
+
+fn sweep(db: &AppDatabase, state: &TempFacetState) -> Result<(), Error> {
+    let time = db.now_time()?;
+    let mut facets = state.0.lock().infer_err()?;
+    let expired = facets
+        .expiry
+        .iter()
+        .filter(|(_, &t)| t <= time)
+        .map(|(f, _)| f.clone())
+        .collect::<Vec<_>>();
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    for f in expired.iter() {
+        let _opstamp = writer.delete_term(Term::from_facet(
+            db.get_field(Fields::Type),
+            &TypeFacet::Temp(f.clone().into()).facet(),
+        ));
+        let _ = facets.expiry.remove(f);
+    }
+    replace_expiry_doc(db, &writer, &facets)?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+fn set_expiry(
+    db: &AppDatabase,
+    state: &TempFacetState,
+    facet: &str,
+    ttl: Option<u64>,
+) -> Result<(), Error> {
+    let mut facets = state.0.lock().infer_err()?;
+    match ttl {
+        Some(ttl) => {
+            let _ = facets.expiry.insert(facet.to_owned(), db.now_time()? + ttl);
+        }
+        None => {
+            let _ = facets.expiry.remove(facet);
+        }
+    }
+    let mut writer = db.index_writer.write().infer_err()?;
+    replace_expiry_doc(db, &writer, &facets)?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+// facets created by new_temp_facet are named /temp/<uuid>
+fn is_expiring(facet: &str) -> bool {
+    facet
+        .strip_prefix("/temp/")
+        .is_some_and(|id| uuid::Uuid::parse_str(id).is_ok())
+}
+
+// gives the default ttl to facets created by new_temp_facet that have none
+fn expire_existing(db: &AppDatabase, facets: &mut TempFacets) -> Result<(), Error> {
+    let searcher = db.get_searcher();
+    let docs = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &"/temp".facet()),
+                IndexRecordOption::Basic,
+            ),
+            &DocSetCollector,
+        )
+        .infer_err()?;
+    let expiry = db.now_time()? + DEFAULT_TTL;
+    for address in docs {
+        let doc = searcher.doc(address).infer_err()?;
+        let Some(facet) = doc
+            .get_first(db.get_field(Fields::Type))
+            .and_then(|f| f.as_facet())
+            .map(|f| f.to_path_string())
+            .filter(|f| is_expiring(f))
+        else {
+            continue;
+        };
+        let _ = facets.expiry.entry(facet).or_insert(expiry);
+    }
+    facets.existing_expire = true;
+
+    let mut writer = db.index_writer.write().infer_err()?;
+    replace_expiry_doc(db, &writer, facets)?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+fn expiry_facet() -> Facet {
+    TypeFacet::Internal("/app_data/temp_facets".into()).facet()
+}
+
+fn load_expiry(db: &AppDatabase) -> Result<TempFacets, Error> {
+    let searcher = db.get_searcher();
+    let facets = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &expiry_facet()),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(1),
+        )
+        .infer_err()?
+        .first()
+        .and_then(|&(_, add)| searcher.doc(add).ok())
+        .and_then(|mut doc| DbAble::take(db, &mut doc).ok())
+        .unwrap_or_default();
+    Ok(facets)
+}
+
+fn replace_expiry_doc(
+    db: &AppDatabase,
+    writer: &tantivy::IndexWriter,
+    facets: &TempFacets,
+) -> Result<(), Error> {
+    let mut doc = Document::new();
+    doc.add_facet(db.get_field(Fields::Type), expiry_facet());
+    facets.clone().add(db, &mut doc)?;
+    let _opstamp = writer.delete_term(Term::from_facet(
+        db.get_field(Fields::Type),
+        &expiry_facet(),
+    ));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{commit, test_db};
+
+    #[tokio::test]
+    async fn existing_temp_facets_expire() {
+        let (_dir, db) = test_db().await;
+        let temp = format!("/temp/{}", uuid::Uuid::new_v4().hyphenated());
+        for facet in [temp.as_str(), "/temp/tachi/chapters/1"] {
+            let mut doc = Document::new();
+            doc.add_facet(db.get_field(Fields::Type), facet.facet());
+            let _opstamp = db.index_writer.read().unwrap().add_document(doc).unwrap();
+        }
+        commit(&db);
+
+        let mut facets = load_expiry(&db).unwrap();
+        assert!(!facets.existing_expire);
+        expire_existing(&db, &mut facets).unwrap();
+        db.index_reader.reload().unwrap();
+
+        let facets = load_expiry(&db).unwrap();
+        assert!(facets.existing_expire);
+        assert_eq!(facets.expiry.keys().collect::<Vec<_>>(), [&temp]);
+    }
+}
@@ -0,0 +1,181 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - words are split at anything that is not alphanumeric ('_' and '-' included)
+// - CJK text has no spaces, so runs of CJK chars are indexed as overlapping bigrams
+// - the prefix tokenizer also emits every prefix of a word for search as you type
+
+use tantivy::{
+    schema::{IndexRecordOption, TextFieldIndexing, TextOptions},
+    tokenizer::{
+        AsciiFoldingFilter, BoxTokenStream, LowerCaser, RemoveLongFilter, TextAnalyzer, Token,
+        TokenStream, Tokenizer,
+    },
+    Index,
+};
+
+pub const TEXT_TOKENIZER: &str = "kolekk_text";
+pub const PREFIX_TOKENIZER: &str = "kolekk_prefix";
+
+// longer prefixes than this are not indexed
+pub const MAX_PREFIX_LEN: usize = 15;
+
+pub fn register_tokenizers(index: &Index) {
+    index.tokenizers().register(
+        TEXT_TOKENIZER,
+        TextAnalyzer::from(WordTokenizer { prefixes: false })
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(AsciiFoldingFilter),
+    );
+    index.tokenizers().register(
+        PREFIX_TOKENIZER,
+        TextAnalyzer::from(WordTokenizer { prefixes: true })
+            .filter(LowerCaser)
+            .filter(AsciiFoldingFilter),
+    );
+}
+
+pub fn text_options() -> TextOptions {
+    TextOptions::default()
+        .set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TEXT_TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        )
+        .set_stored()
+}
+
+pub fn prefix_options() -> TextOptions {
+    TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(PREFIX_TOKENIZER)
+            .set_index_option(IndexRecordOption::Basic),
+    )
+}
+
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30ff // hiragana, katakana
+        | 0x3400..=0x4dbf // cjk extension a
+        | 0x4e00..=0x9fff // cjk unified ideographs
+        | 0xac00..=0xd7af // hangul syllables
+        | 0xf900..=0xfaff // cjk compatibility ideographs
+        | 0xff66..=0xff9f // halfwidth katakana
+        | 0x20000..=0x2fa1f // cjk extensions b..
+    )
+}
+
+#[derive(Clone)]
+pub struct WordTokenizer {
+    prefixes: bool,
+}
+
+impl Tokenizer for WordTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(VecTokenStream {
+            tokens: self.tokens(text),
+            next: 0,
+        })
+    }
+}
+
+impl WordTokenizer {
+    fn tokens(&self, text: &str) -> Vec<Token> {
+        let mut tokens = vec![];
+        let mut position = 0;
+        let mut word = None;
+        let mut cjk = vec![];
+
+        // the trailing ' ' flushes the last word
+        for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            if is_cjk(c) {
+                if let Some(start) = word.take() {
+                    self.push_word(&mut tokens, &mut position, text, start, i);
+                }
+                cjk.push((i, c));
+            } else {
+                if !cjk.is_empty() {
+                    self.push_cjk(&mut tokens, &mut position, &cjk);
+                    cjk.clear();
+                }
+                if c.is_alphanumeric() {
+                    let _ = word.get_or_insert(i);
+                } else if let Some(start) = word.take() {
+                    self.push_word(&mut tokens, &mut position, text, start, i);
+                }
+            }
+        }
+        tokens
+    }
+
+    fn push_word(
+        &self,
+        tokens: &mut Vec<Token>,
+        position: &mut usize,
+        text: &str,
+        from: usize,
+        to: usize,
+    ) {
+        let word = &text[from..to];
+        if self.prefixes {
+            for (end, _) in word
+                .char_indices()
+                .skip(1)
+                .chain([(word.len(), ' ')])
+                .take(MAX_PREFIX_LEN)
+            {
+                tokens.push(token(&word[..end], from, from + end, *position));
+            }
+        } else {
+            tokens.push(token(word, from, to, *position));
+        }
+        *position += 1;
+    }
+
+    fn push_cjk(&self, tokens: &mut Vec<Token>, position: &mut usize, chars: &[(usize, char)]) {
+        let end = |i: usize, c: char| i + c.len_utf8();
+        if chars.len() == 1 || self.prefixes {
+            // single chars are needed for prefix matching
+            for &(i, c) in chars {
+                tokens.push(token(&c.to_string(), i, end(i, c), *position));
+                *position += 1;
+            }
+        }
+        for w in chars.windows(2) {
+            let ((i, a), (j, b)) = (w[0], w[1]);
+            tokens.push(token(&format!("{}{}", a, b), i, end(j, b), *position));
+            *position += 1;
+        }
+    }
+}
+
+fn token(text: &str, offset_from: usize, offset_to: usize, position: usize) -> Token {
+    Token {
+        offset_from,
+        offset_to,
+        position,
+        text: text.to_owned(),
+        position_length: 1,
+    }
+}
+
+struct VecTokenStream {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl TokenStream for VecTokenStream {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
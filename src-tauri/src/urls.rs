@@ -0,0 +1,340 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - every bookmark has a Fields::Key: its url in a canonical form. bookmarks with the same
+//   key are duplicates.
+// - http and https are treated the same. hosts are lowercased, default ports, fragments,
+//   trailing slashes and tracking parameters are removed.
+// - the rel=canonical url of the page is used if it is on the same site.
+// - keys of existing bookmarks are recomputed by merge_duplicate_bookmarks, so it should be
+//   run after the list of tracking parameters changes.
+
+use std::collections::{HashMap, HashSet};
+
+use kolekk_types::objects::{
+    Bookmark, Fields, Id, Indexed, SearchableEntry, TypeFacet, WithContext,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tantivy::{collector::TopDocs, query::TermQuery, schema::IndexRecordOption, Document, Term};
+use tauri::State;
+
+use crate::{
+    archive::{archive_path, delete_archive},
+    bad_error::{Error, InferBadError},
+    batch::objects_of_type,
+    config::AppConfig,
+    database::{delete_object, AppDatabase, AutoDbAble, DbAble, FacetFrom, RObject},
+    filesystem::get_path,
+};
+
+// a trailing '*' matches any parameter that starts with the rest
+const DEFAULT_TRACKING_PARAMS: [&str; 15] = [
+    "utm_*", "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "mc_cid", "mc_eid",
+    "igshid", "yclid", "_hsenc", "_hsmi", "mkt_tok", "ref_src",
+];
+
+#[derive(Serialize, Deserialize, Clone)]
+struct TrackingParams {
+    params: Vec<String>,
+}
+impl AutoDbAble for TrackingParams {}
+
+impl Default for TrackingParams {
+    fn default() -> Self {
+        Self {
+            params: DEFAULT_TRACKING_PARAMS.map(String::from).to_vec(),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_tracking_params(db: State<'_, AppDatabase>) -> Result<Vec<String>, Error> {
+    tracking_params(db.inner())
+}
+
+#[tauri::command]
+pub async fn set_tracking_params(
+    db: State<'_, AppDatabase>,
+    params: Vec<String>,
+) -> Result<(), Error> {
+    let db = db.inner();
+    let params = params
+        .into_iter()
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty())
+        .collect();
+    let mut doc = Document::new();
+    doc.add_facet(db.get_field(Fields::Type), params_facet());
+    TrackingParams { params }.add(db, &mut doc)?;
+    let mut writer = db.index_writer.write().infer_err()?;
+    let _opstamp = writer.delete_term(Term::from_facet(
+        db.get_field(Fields::Type),
+        &params_facet(),
+    ));
+    let _opstamp = writer.add_document(doc).infer_err()?;
+    let _opstamp = writer.commit().infer_err()?;
+    Ok(())
+}
+
+// merges bookmarks with the same key into one and updates outdated keys.
+// returns the kept bookmarks with the ids of the ones merged into them
+#[tauri::command]
+pub async fn merge_duplicate_bookmarks(
+    db: State<'_, AppDatabase>,
+    config: State<'_, AppConfig>,
+) -> Result<Vec<WithContext<Id, Vec<Id>>>, Error> {
+    let db = db.inner();
+    let config = config.inner();
+    let tracking = tracking_params(db)?;
+
+    // held while reading, so that background jobs can't change the bookmarks in between
+    let mut writer = db.index_writer.write().infer_err()?;
+    // key -> (bookmark, key changed)
+    let mut groups = HashMap::<String, Vec<(RObject<Bookmark>, bool)>>::new();
+    for mut bk in objects_of_type::<RObject<Bookmark>>(db, TypeFacet::Bookmark)? {
+        let key = bookmark_key(
+            &bk.data.data.data.url,
+            bk.data.data.data.canonical.as_deref(),
+            &tracking,
+        );
+        let changed = get_key(&bk.data.searchable) != Some(key.as_str());
+        if changed {
+            set_key(&mut bk.data.searchable, key.clone());
+        }
+        groups.entry(key).or_default().push((bk, changed));
+    }
+
+    let time = db.now_time()?;
+    let mut merged = vec![];
+    for (_, mut group) in groups {
+        // bookmarks from sources get recreated when the source changes, so one that was
+        // added by hand is kept if there is one
+        group.sort_by_key(|(b, _)| (b.data.data.data.source.is_some(), b.ctime, b.id));
+        let mut group = group.into_iter();
+        let Some((mut keep, changed)) = group.next() else {
+            continue;
+        };
+        let mut removed = vec![];
+        for (other, _) in group {
+            removed.push(other.id);
+            merge_into(&mut keep, other);
+        }
+        // the kept bookmark can get the archive of one that is merged into it
+        if let Some(archive) = keep.data.data.data.archive.as_mut() {
+            if removed
+                .iter()
+                .any(|&id| archive.path.path == archive_path(id).path)
+            {
+                let path = archive_path(keep.id);
+                std::fs::rename(get_path(&archive.path, config), get_path(&path, config))
+                    .infer_err()?;
+                archive.path = path;
+            }
+        }
+        for &id in removed.iter() {
+            delete_object(db, &writer, id, time);
+        }
+        if changed || !removed.is_empty() {
+            keep.last_update = time;
+            let id = keep.id;
+            let mut doc = Document::new();
+            keep.add(db, &mut doc)?;
+            let _opstamp =
+                writer.delete_term(Term::from_field_u64(db.get_field(Fields::Id), id as _));
+            let _opstamp = writer.add_document(doc).infer_err()?;
+        }
+        if !removed.is_empty() {
+            merged.push(WithContext {
+                data: keep.id,
+                context: removed,
+            });
+        }
+    }
+    let _opstamp = writer.commit().infer_err()?;
+    for id in merged.iter().flat_map(|m| m.context.iter()) {
+        let _ = delete_archive(config, *id).dbg();
+    }
+    Ok(merged)
+}
+
+pub fn canonical_url(url: &str, tracking: &[String]) -> String {
+    let url = url.trim();
+    let u = match Url::parse(url) {
+        Ok(u) if !u.cannot_be_a_base() => u,
+        Ok(u) => return u.to_string(),
+        Err(_) => return url.to_string(),
+    };
+
+    let mut key = match u.scheme() {
+        "http" | "https" => String::new(),
+        s => format!("{}://", s),
+    };
+    // Url lowercases the host and has no port if it is the default one
+    key.push_str(u.host_str().unwrap_or_default());
+    if let Some(port) = u.port() {
+        key.push_str(&format!(":{}", port));
+    }
+    key.push_str(u.path().trim_end_matches('/'));
+
+    let query = u
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .filter(|p| !is_tracking(p.split('=').next().unwrap_or_default(), tracking))
+        .collect::<Vec<_>>();
+    if !query.is_empty() {
+        key.push('?');
+        key.push_str(&query.join("&"));
+    }
+    key
+}
+
+// some sites point the rel=canonical of every page to the home page, so it is only used if
+// it is on the same site and not less specific than the url
+pub fn bookmark_key(url: &str, canonical: Option<&str>, tracking: &[String]) -> String {
+    let site = |u: &Url| {
+        u.host_str()
+            .map(|h| h.trim_start_matches("www.").to_string())
+    };
+    let canonical = canonical.and_then(|c| {
+        let (u, c) = (Url::parse(url).ok()?, Url::parse(c).ok()?);
+        let same_site = site(&u) == site(&c);
+        let home_page = c.path() == "/" && u.path() != "/";
+        (same_site && !home_page).then_some(c)
+    });
+    match canonical {
+        Some(c) => canonical_url(c.as_str(), tracking),
+        None => canonical_url(url, tracking),
+    }
+}
+
+pub fn set_key(searchable: &mut Vec<Indexed>, key: String) {
+    searchable.retain(|i| !matches!(i.field, Fields::Key));
+    searchable.push(Indexed {
+        field: Fields::Key,
+        data: key.into(),
+    });
+}
+
+// for bookmarks that are json objects
+pub fn set_bookmark_key(bk: &mut SearchableEntry<Map<String, Value>>, tracking: &[String]) {
+    let Some(url) = bk.data.get("url").and_then(Value::as_str) else {
+        return;
+    };
+    let canonical = bk.data.get("canonical").and_then(Value::as_str);
+    let key = bookmark_key(url, canonical, tracking);
+    set_key(&mut bk.searchable, key);
+}
+
+pub fn get_key(searchable: &[Indexed]) -> Option<&str> {
+    searchable
+        .iter()
+        .find(|i| matches!(i.field, Fields::Key))
+        .and_then(|i| i.data.as_str())
+}
+
+pub fn find_bookmark(db: &AppDatabase, key: &str) -> Result<Option<RObject<Bookmark>>, Error> {
+    let searcher = db.get_searcher();
+    let res = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_field_text(db.get_field(Fields::Key), key),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(1),
+        )
+        .infer_err()?;
+    match res.first() {
+        Some(&(_, address)) => {
+            let mut doc = searcher.doc(address).infer_err()?;
+            Ok(Some(DbAble::take(db, &mut doc)?))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn tracking_params(db: &AppDatabase) -> Result<Vec<String>, Error> {
+    let searcher = db.get_searcher();
+    let params: TrackingParams = searcher
+        .search(
+            &TermQuery::new(
+                Term::from_facet(db.get_field(Fields::Type), &params_facet()),
+                IndexRecordOption::Basic,
+            ),
+            &TopDocs::with_limit(1),
+        )
+        .infer_err()?
+        .first()
+        .and_then(|&(_, add)| searcher.doc(add).ok())
+        .and_then(|mut doc| DbAble::take(db, &mut doc).ok())
+        .unwrap_or_default();
+    Ok(params.params)
+}
+
+pub fn default_tracking_params() -> Vec<String> {
+    TrackingParams::default().params
+}
+
+fn is_tracking(param: &str, tracking: &[String]) -> bool {
+    let param = param.to_lowercase();
+    tracking.iter().any(|t| match t.strip_suffix('*') {
+        Some(prefix) => param.starts_with(prefix),
+        None => param == *t,
+    })
+}
+
+fn params_facet() -> tantivy::schema::Facet {
+    TypeFacet::Internal("/app_data/tracking_params".into()).facet()
+}
+
+fn merge_into(keep: &mut RObject<Bookmark>, other: RObject<Bookmark>) {
+    let tags = keep.data.data.tags.iter().cloned().collect::<HashSet<_>>();
+    keep.data.data.tags.extend(
+        other
+            .data
+            .data
+            .tags
+            .iter()
+            .filter(|t| !tags.contains(t))
+            .cloned(),
+    );
+
+    for i in other.data.searchable {
+        let has_body = keep
+            .data
+            .searchable
+            .iter()
+            .any(|k| matches!(k.field, Fields::Body));
+        let keep_it = match i.field {
+            Fields::Text => !keep.data.searchable.iter().any(|k| k.data == i.data),
+            Fields::Body => !has_body,
+            _ => false,
+        };
+        if keep_it {
+            keep.data.searchable.push(i);
+        }
+    }
+
+    let (k, o) = (&mut keep.data.data.data, other.data.data.data);
+    fill(&mut k.title, o.title);
+    fill(&mut k.description, o.description);
+    fill(&mut k.link, o.link);
+    fill(&mut k.archive, o.archive);
+    fill(&mut k.crawled, o.crawled);
+    fill(&mut k.image, o.image);
+    fill(&mut k.icon, o.icon);
+    fill(&mut k.site_name, o.site_name);
+    fill(&mut k.canonical, o.canonical);
+
+    keep.ctime = keep.ctime.min(other.ctime);
+    keep.last_interaction = keep.last_interaction.max(other.last_interaction);
+}
+
+fn fill<T>(keep: &mut Option<T>, other: Option<T>) {
+    if keep.is_none() {
+        *keep = other;
+    }
+}
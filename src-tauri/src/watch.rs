@@ -0,0 +1,131 @@
+#[allow(unused_imports)]
+use crate::{dbg, debug, error};
+
+// - the directories of bookmark sources are watched, not the files themselves. editors (and
+//   browsers) often save by replacing the file, which would end a watch on the old file.
+// - a source that is a directory (an obsidian vault) is watched recursively.
+// - changes are collected until the files have been quiet for a bit, then the sources whose
+//   mtime changed are imported again and "bookmark-source-refreshed" is emitted for each.
+
+use std::{
+    collections::HashSet,
+    path::{Path as FsPath, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Manager};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::{
+    bad_error::{BadError, Error, InferBadError},
+    config::AppConfig,
+    database::AppDatabase,
+    filesystem::get_path,
+    sources::{bookmark_sources, refresh_source, source_modified},
+};
+
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+pub struct SourceWatcher {
+    // None if the os would not give us one. sources then only get refreshed manually
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    dirs: Mutex<HashSet<PathBuf>>,
+    // the watcher and refresh_bookmark_sources should not import the same source together
+    pub refreshing: tokio::sync::Mutex<()>,
+}
+
+impl SourceWatcher {
+    pub fn watch(&self, file: &FsPath) -> Result<(), Error> {
+        let (dir, mode) = if file.is_dir() {
+            (file, RecursiveMode::Recursive)
+        } else {
+            let dir = file
+                .parent()
+                .bad_err("bookmark source has no parent directory")?;
+            (dir, RecursiveMode::NonRecursive)
+        };
+        let mut dirs = self.dirs.lock().infer_err()?;
+        if dirs.contains(dir) {
+            return Ok(());
+        }
+        if let Some(w) = self.watcher.lock().infer_err()?.as_mut() {
+            w.watch(dir, mode).infer_err()?;
+        }
+        let _ = dirs.insert(dir.to_path_buf());
+        Ok(())
+    }
+}
+
+pub fn init_source_watcher(
+    app_handle: &AppHandle,
+    conf: &AppConfig,
+    db: &AppDatabase,
+) -> Result<(), Error> {
+    let (tx, rx) = unbounded_channel();
+    let watcher = notify::recommended_watcher(move |e: notify::Result<Event>| {
+        let Ok(e) = e.infer_err().dbg() else {
+            return;
+        };
+        // reading the file while importing it should not trigger another import
+        if matches!(
+            e.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            for p in e.paths {
+                let _ = tx.send(p);
+            }
+        }
+    })
+    .infer_err()
+    .dbg()
+    .ok();
+
+    let state = SourceWatcher {
+        watcher: Mutex::new(watcher),
+        dirs: Default::default(),
+        refreshing: Default::default(),
+    };
+    for source in bookmark_sources(db)? {
+        let _ = state.watch(&get_path(&source.data.path, conf)).dbg();
+    }
+    app_handle.manage(state);
+
+    let _ = tokio::task::spawn(refresh_on_change(app_handle.app_handle(), rx));
+    Ok(())
+}
+
+async fn refresh_on_change(handle: AppHandle, mut rx: UnboundedReceiver<PathBuf>) {
+    while let Some(path) = rx.recv().await {
+        let mut changed = HashSet::from([path]);
+        while let Ok(Some(path)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+            let _ = changed.insert(path);
+        }
+        let _ = refresh_changed(&handle, &changed).await.dbg();
+    }
+}
+
+async fn refresh_changed(handle: &AppHandle, changed: &HashSet<PathBuf>) -> Result<(), Error> {
+    let db = handle.state::<AppDatabase>();
+    let config = handle.state::<AppConfig>();
+    let watcher = handle.state::<SourceWatcher>();
+
+    let _lock = watcher.refreshing.lock().await;
+    for source in bookmark_sources(db.inner())? {
+        let path = get_path(&source.data.path, config.inner());
+        // for a vault, any change inside of it
+        if !changed.iter().any(|p| p.starts_with(&path))
+            || !source_modified(&source, config.inner())
+        {
+            continue;
+        }
+        if let Ok(report) = refresh_source(handle, source).dbg() {
+            let _ = handle
+                .emit_all("bookmark-source-refreshed", report)
+                .infer_err()
+                .dbg();
+        }
+    }
+    Ok(())
+}